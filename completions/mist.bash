@@ -4,9 +4,12 @@ _mist_get_pkglist() {
     fi
 
     if ! printf '%s\n' "${@}" "${words[@]}" | grep -q -- '--apt-only' || ! printf '%s\n' "${opts[@]}" | grep -q -- '--apt-only'; then
-        if [[ -f '/var/cache/mist/pkglist.gz' ]]; then
-            mapfile -O "${#COMPREPLY[@]}" -t COMPREPLY < <(gzip -cd '/var/cache/mist/pkglist.gz' | grep "^${@: -1}")
-        fi
+        local pkglist
+        for pkglist in /var/cache/mist/repos/*/pkglist.gz; do
+            if [[ -f "${pkglist}" ]]; then
+                mapfile -O "${#COMPREPLY[@]}" -t COMPREPLY < <(gzip -cd "${pkglist}" | grep "^${@: -1}")
+            fi
+        done
     fi
 }
 
@@ -27,16 +30,28 @@ _mist() {
     _init_completion || return
 
     local cmds=(
+        'build'
+        'clean'
         'clone'
         'comment'
+        'flag'
         'help'
+        'history'
+        'hold'
+        'info'
         'install'
         'list'
         'list-comments'
+        'pin'
         'remove'
+        'repo'
         'search'
+        'unflag'
+        'unhold'
+        'unvote'
         'update'
         'upgrade'
+        'vote'
         'whoami'
     )
 
@@ -70,6 +85,26 @@ _mist() {
             esac
             ;;
         comment)
+            opts=('--delete' '--edit' '--mpr-url' '--msg' '--token')
+
+            case "${prev}" in
+                --token|--mpr-url|--msg|--edit|--delete)
+                    return
+                    ;;
+            esac
+
+            case "${cur}" in
+                -*)
+                    _mist_gen_compreply '${opts[@]}' "${cur}"
+                    return
+                    ;;
+                *)
+                    _mist_pkg_specified_check "${cur}"
+                    return
+                    ;;
+            esac
+            ;;
+        flag)
             opts=('--mpr-url' '--msg' '--token')
 
             case "${prev}" in
@@ -92,13 +127,97 @@ _mist() {
         help)
             return
             ;;
+        build)
+            opts=('--output-dir' '--mpr-url' '--yes' '--assume-yes' '--no-review' '--keep-build-deps' '--offline' '--simulate' '--dry-run' '--output')
+
+            case "${prev}" in
+                --output-dir)
+                    _filedir -d
+                    return
+                    ;;
+                --mpr-url)
+                    return
+                    ;;
+            esac
+
+            case "${cur}" in
+                -*)
+                    _mist_gen_compreply '${opts[@]}' "${cur}"
+                    return
+                    ;;
+                *)
+                    _mist_get_pkglist '--mpr-only' "${cur}"
+                    return
+                    ;;
+            esac
+            ;;
+        clean)
+            opts=('--builds' '--sources' '--metadata' '--all')
+            _mist_gen_compreply '${opts[@]}' "${cur}"
+            return
+            ;;
+        repo)
+            if [[ "${#nonopts[@]}" == 3 ]]; then
+                opts=('list' 'add' 'remove' 'refresh')
+                _mist_gen_compreply '${opts[@]}' "${cur}"
+            elif [[ "${nonopts[2]}" == 'add' ]]; then
+                _filedir deb
+            fi
+
+            return
+            ;;
+        history)
+            if [[ "${#nonopts[@]}" == 3 ]]; then
+                opts=('list' 'show' 'undo')
+                _mist_gen_compreply '${opts[@]}' "${cur}"
+            elif [[ "${nonopts[2]}" == 'undo' ]]; then
+                opts=('--yes' '--assume-yes')
+                _mist_gen_compreply '${opts[@]}' "${cur}"
+            fi
+
+            return
+            ;;
+        hold|unhold|pin)
+            _mist_get_pkglist '--apt-only' "${cur}"
+            return
+            ;;
+        info|show)
+            opts=('--mpr-url' '--token')
+
+            case "${prev}" in
+                --mpr-url|--token)
+                    return
+                    ;;
+            esac
+
+            case "${cur}" in
+                -*)
+                    _mist_gen_compreply '${opts[@]}' "${cur}"
+                    return
+                    ;;
+                *)
+                    _mist_get_pkglist "${cur}"
+                    return
+                    ;;
+            esac
+            ;;
         install)
-            opts=('--mpr-url')
+            opts=('--mpr-url' '--prefer' '--yes' '--assume-yes' '--no-review' '--keep-build-deps' '--offline' '--simulate' '--dry-run' '--output')
 
             case "${prev}" in
                 --mpr-url)
                 return
                 ;;
+                --output)
+                    opts=('text' 'json')
+                    _mist_gen_compreply '${opts[@]}' "${cur}"
+                    return
+                    ;;
+                --prefer)
+                    opts=('apt' 'mpr')
+                    _mist_gen_compreply '${opts[@]}' "${cur}"
+                    return
+                    ;;
             esac
 
             case "${cur}" in
@@ -113,10 +232,10 @@ _mist() {
             esac
             ;;
         list-comments)
-            opts=('--mpr-url' '--paging')
+            opts=('--author' '--json' '--limit' '--mpr-url' '--paging' '--since')
 
             case "${prev}" in
-                --mpr-url)
+                --mpr-url|--author|--limit|--since)
                     return
                     ;;
                 --paging)
@@ -138,7 +257,15 @@ _mist() {
             esac
             ;;
         remove)
-            opts=('--autoremove' '--purge')
+            opts=('--autoremove' '--purge' '--yes' '--assume-yes' '--simulate' '--dry-run' '--output')
+
+            case "${prev}" in
+                --output)
+                    opts=('text' 'json')
+                    _mist_gen_compreply '${opts[@]}' "${cur}"
+                    return
+                    ;;
+            esac
 
             case "${cur}" in
             -*)
@@ -153,10 +280,29 @@ _mist() {
             ;;
 
         search|list)
-            opts=('--mpr-url' '--apt-only' '--mpr-only' '--name-only' '--installed')
+            opts=('--mpr-url' '--apt-only' '--mpr-only' '--name-only' '--installed' '--voted' '--token' '--output')
+
+            if [[ "${nonopts[1]}" == 'search' ]]; then
+                opts+=('--sort' '--limit')
+            else
+                opts+=('--maintainer')
+            fi
 
             case "${prev}" in
-                --mpr-url)
+                --mpr-url|--token)
+                    return
+                    ;;
+                --output)
+                    opts=('text' 'json' 'tsv')
+                    _mist_gen_compreply '${opts[@]}' "${cur}"
+                    return
+                    ;;
+                --sort)
+                    opts=('name' 'votes' 'popularity' 'relevance')
+                    _mist_gen_compreply '${opts[@]}' "${cur}"
+                    return
+                    ;;
+                --limit|--maintainer)
                     return
                     ;;
             esac
@@ -185,17 +331,42 @@ _mist() {
             return
             ;;
         upgrade)
-            opts=('--apt-only' '--mpr-only' '--mpr-url')
+            opts=('--apt-only' '--mpr-only' '--mpr-url' '--yes' '--assume-yes' '--no-review' '--keep-build-deps' '--offline' '--simulate' '--dry-run' '--output')
 
             case "${prev}" in
                 --mpr-url)
                     return
                     ;;
+                --output)
+                    opts=('text' 'json')
+                    _mist_gen_compreply '${opts[@]}' "${cur}"
+                    return
+                    ;;
             esac
 
             _mist_gen_compreply '${opts[@]}' "${cur}"
             return
             ;;
+        vote|unvote|unflag)
+            opts=('--mpr-url' '--token')
+
+            case "${prev}" in
+                --token|--mpr-url)
+                    return
+                    ;;
+            esac
+
+            case "${cur}" in
+                -*)
+                    _mist_gen_compreply '${opts[@]}' "${cur}"
+                    return
+                    ;;
+                *)
+                    _mist_pkg_specified_check "${cur}"
+                    return
+                    ;;
+            esac
+            ;;
         whoami)
             opts=('--token' '--mpr-url')
 
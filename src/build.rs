@@ -0,0 +1,75 @@
+use crate::{
+    cache::{Cache, CommitOptions, MprCache},
+    install_util, message,
+    style::Colorize,
+    util,
+};
+use rust_apt::cache::Cache as AptCache;
+use std::{env, path::PathBuf};
+
+pub fn build(args: &clap::ArgMatches) {
+    let pkglist: Vec<&str> = args
+        .get_many::<String>("pkg")
+        .unwrap()
+        .map(|pkg| pkg.as_str())
+        .collect();
+    let mpr_url: &String = args.get_one("mpr-url").unwrap();
+    let output_dir: &PathBuf = args.get_one("output-dir").unwrap();
+
+    // Packages get built from their Git checkouts, so a relative output directory
+    // needs to be resolved now.
+    let mut options = CommitOptions::from_args(args);
+    options.output_dir = Some(env::current_dir().unwrap().join(output_dir));
+
+    // Packages passed as `reponame/pkg` always come from that repository.
+    let mut mpr_cache = MprCache::new();
+    let pkglist = mpr_cache.select_repos(&pkglist);
+    let cache = Cache::new(AptCache::new(), mpr_cache);
+
+    // Only MPR packages can be built, so make sure they all exist before doing
+    // anything else.
+    let mut unfindable = false;
+
+    for pkg in &pkglist {
+        if !cache.mpr_cache().has_pkgname(pkg) {
+            message::error(&format!(
+                "Unable to find MPR package '{}'.\n",
+                pkg.green().bold()
+            ));
+            unfindable = true;
+        }
+    }
+
+    if unfindable {
+        quit::with_code(exitcode::USAGE);
+    }
+
+    // Get the order to build MPR packages in, which also marks any APT packages
+    // needed to build them for installation.
+    let mut mpr_build_order = install_util::order_mpr_packages(&cache, &pkglist);
+
+    // Packages that are already installed don't get marked for installation, but
+    // still need to be built.
+    let already_installed: Vec<String> = pkglist
+        .iter()
+        .filter(|pkg| {
+            !mpr_build_order
+                .iter()
+                .flatten()
+                .any(|ordered| ordered == *pkg)
+        })
+        .map(|pkg| pkg.to_string())
+        .collect();
+
+    if !already_installed.is_empty() {
+        mpr_build_order.push(already_installed);
+    }
+
+    // Make sure any new marked APT packages are resolved properly.
+    if let Err(err) = cache.apt_cache().resolve(true) {
+        util::handle_errors(&err);
+        quit::with_code(exitcode::UNAVAILABLE);
+    }
+
+    cache.commit(&mpr_build_order, mpr_url, &options, &[]);
+}
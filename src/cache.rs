@@ -1,6 +1,11 @@
 use crate::{
+    config::{MprRepository, CONFIG, MPR_REPO_NAME},
+    history::{self, BuiltPackage, MprBuild, TransactionRecorder},
     install_util, message,
     progress::{MistAcquireProgress, MistInstallProgress},
+    query,
+    repo::Repo,
+    review,
     style::Colorize,
     util,
 };
@@ -8,26 +13,31 @@ use rust_apt::{
     cache::{Cache as AptCache, PackageSort},
     package::Package,
     progress::{AcquireProgress, InstallProgress},
-    tagfile::TagSection,
 };
 use serde::{Deserialize, Serialize};
 use std::io::prelude::*;
-use std::{collections::HashMap, env, fs, io};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 ///////////////////////////
 // Stuff for MPR caches. //
 ///////////////////////////
-#[derive(Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct MprDependencyGroup {
     #[serde(rename = "Distro")]
-    distro: Option<String>,
+    pub distro: Option<String>,
     #[serde(rename = "Arch")]
-    arch: Option<String>,
+    pub arch: Option<String>,
     #[serde(rename = "Packages")]
-    packages: Vec<String>,
+    pub packages: Vec<String>,
 }
 
-#[derive(Deserialize, Serialize, PartialEq)]
+#[derive(Clone, Deserialize, Serialize, PartialEq)]
 pub struct MprPackage {
     #[serde(rename = "Name")]
     pub pkgname: String,
@@ -55,6 +65,10 @@ pub struct MprPackage {
     pub conflicts: Vec<MprDependencyGroup>,
     #[serde(rename = "Provides")]
     pub provides: Vec<MprDependencyGroup>,
+    /// The name of the repository the package comes from. This isn't part of
+    /// the metadata from the repository itself, and gets set when it's loaded.
+    #[serde(rename = "Repository", default)]
+    pub repo: String,
 }
 
 impl MprPackage {
@@ -154,11 +168,91 @@ impl MprPackage {
     }
 }
 
-pub struct MprCache {
-    packages: HashMap<String, MprPackage>,
+/// The bytes at the start of the binary MPR cache, used to recognize it.
+const MPR_INDEX_MAGIC: &[u8; 4] = b"MIST";
+
+/// The version of the binary MPR cache's format. This needs to be bumped
+/// whenever [`MprPackage`] or [`MprIndexes`] change.
+const MPR_INDEX_VERSION: u32 = 2;
+
+/// The length of the binary MPR cache's header: the magic bytes, the format
+/// version, and the length of the serialized packages.
+const MPR_INDEX_HEADER_LEN: usize = 16;
+
+/// Lookup tables for the MPR cache, so that commands don't need to scan (or
+/// even load) every package to find the ones they want.
+#[derive(Default, Deserialize, Serialize)]
+struct MprIndexes {
+    /// The pkgbase of each pkgname.
+    pkgname_pkgbases: HashMap<String, String>,
+    /// The pkgnames built by each pkgbase.
+    pkgbases: HashMap<String, Vec<String>>,
+    /// The pkgnames that provide each package, in any distro/architecture
+    /// variant.
+    provides: HashMap<String, Vec<String>>,
+    /// The pkgnames maintained by each user.
+    maintainers: HashMap<String, Vec<String>>,
 }
 
-impl MprCache {
+impl MprIndexes {
+    fn new(packages: &HashMap<String, MprPackage>) -> Self {
+        let mut indexes = Self::default();
+
+        for pkg in packages.values() {
+            indexes
+                .pkgname_pkgbases
+                .insert(pkg.pkgname.clone(), pkg.pkgbase.clone());
+            indexes
+                .pkgbases
+                .entry(pkg.pkgbase.clone())
+                .or_default()
+                .push(pkg.pkgname.clone());
+
+            if let Some(maintainer) = &pkg.maintainer {
+                indexes
+                    .maintainers
+                    .entry(maintainer.clone())
+                    .or_default()
+                    .push(pkg.pkgname.clone());
+            }
+
+            for dep_group in &pkg.provides {
+                for provide in &dep_group.packages {
+                    let provide_name = provide.split(['<', '>', '=']).next().unwrap().trim();
+                    let providers = indexes.provides.entry(provide_name.to_owned()).or_default();
+
+                    if !providers.contains(&pkg.pkgname) {
+                        providers.push(pkg.pkgname.clone());
+                    }
+                }
+            }
+        }
+
+        // Sort everything so lookups return packages in a stable order.
+        for pkgnames in indexes
+            .pkgbases
+            .values_mut()
+            .chain(indexes.provides.values_mut())
+            .chain(indexes.maintainers.values_mut())
+        {
+            pkgnames.sort();
+        }
+
+        indexes
+    }
+}
+
+/// The MPR cache of a single repository.
+pub struct MprRepoCache {
+    repo: MprRepository,
+    /// The serialized packages from the binary MPR cache, which only get
+    /// deserialized once they're needed.
+    packages_data: Vec<u8>,
+    packages: OnceLock<HashMap<String, MprPackage>>,
+    indexes: MprIndexes,
+}
+
+impl MprRepoCache {
     // Convert a Vector of MPR packages (the way they're stored on the MPR itself)
     // into a HashMap that's accessible via key-value pairs.
     fn vec_to_map(packages: Vec<MprPackage>) -> HashMap<String, MprPackage> {
@@ -172,34 +266,141 @@ impl MprCache {
         map
     }
 
-    pub fn validate_data(data: &[u8]) -> Result<Self, ()> {
+    pub fn validate_data(repo: &MprRepository, data: &[u8]) -> Result<Self, ()> {
         let packages = match String::from_utf8(data.to_vec()) {
             Ok(string) => string,
             Err(_) => return Err(()),
         };
 
-        let cache = match serde_json::from_str::<Vec<MprPackage>>(&packages) {
+        let mut cache = match serde_json::from_str::<Vec<MprPackage>>(&packages) {
             Ok(json) => json,
             Err(_) => return Err(()),
         };
 
+        for pkg in &mut cache {
+            pkg.repo = repo.name.clone();
+        }
+
+        let packages = Self::vec_to_map(cache);
+
         Ok(Self {
-            packages: Self::vec_to_map(cache),
+            repo: repo.clone(),
+            packages_data: vec![],
+            indexes: MprIndexes::new(&packages),
+            packages: OnceLock::from(packages),
         })
     }
 
-    pub fn new() -> Self {
-        // Try reading the cache file. If it doesn't exist or it's older than five
-        // minutes, we have to update the cache file.
-        let mut cache_file_path = util::xdg::get_global_cache_dir();
+    /// Get the path of the binary MPR cache.
+    fn index_path(repo: &MprRepository) -> PathBuf {
+        let mut index_path = repo.cache_dir();
+        index_path.push("cache.bin");
+        index_path
+    }
+
+    /// Parse the header of the binary MPR cache, returning the length of the
+    /// serialized packages if the cache is usable by this version of Mist.
+    fn parse_header(header: &[u8]) -> Option<usize> {
+        if header.len() < MPR_INDEX_HEADER_LEN || &header[..4] != MPR_INDEX_MAGIC {
+            return None;
+        }
+
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+
+        if version != MPR_INDEX_VERSION {
+            return None;
+        }
+
+        Some(u64::from_le_bytes(header[8..16].try_into().unwrap()) as usize)
+    }
+
+    /// Read the binary MPR cache, only deserializing its indexes.
+    fn from_index(repo: &MprRepository) -> Option<Self> {
+        Self::decode_index(repo, fs::read(Self::index_path(repo)).ok()?)
+    }
+
+    /// Load a cache from the contents of the binary MPR cache, only
+    /// deserializing its indexes.
+    fn decode_index(repo: &MprRepository, data: Vec<u8>) -> Option<Self> {
+        let packages_len = Self::parse_header(&data)?;
+
+        if data.len() < MPR_INDEX_HEADER_LEN + packages_len {
+            return None;
+        }
+
+        let mut packages_data = data;
+        let indexes_data = packages_data.split_off(MPR_INDEX_HEADER_LEN + packages_len);
+        packages_data.drain(..MPR_INDEX_HEADER_LEN);
+
+        Some(Self {
+            repo: repo.clone(),
+            packages_data,
+            packages: OnceLock::new(),
+            indexes: bincode::deserialize(&indexes_data).ok()?,
+        })
+    }
+
+    /// Check if the binary MPR cache exists and is usable by this version of
+    /// Mist.
+    ///
+    /// Only the header is read, so this doesn't need to load the whole cache.
+    pub fn has_index(repo: &MprRepository) -> bool {
+        let Ok(mut file) = fs::File::open(Self::index_path(repo)) else {
+            return false;
+        };
+        let mut header = [0; MPR_INDEX_HEADER_LEN];
+
+        file.read_exact(&mut header).is_ok() && Self::parse_header(&header).is_some()
+    }
+
+    /// Serialize this cache into the binary MPR cache's format.
+    fn encode_index(&self) -> io::Result<Vec<u8>> {
+        let packages_data = bincode::serialize(self.packages()).map_err(io::Error::other)?;
+        let indexes_data = bincode::serialize(&self.indexes).map_err(io::Error::other)?;
+
+        let mut data =
+            Vec::with_capacity(MPR_INDEX_HEADER_LEN + packages_data.len() + indexes_data.len());
+        data.extend_from_slice(MPR_INDEX_MAGIC);
+        data.extend_from_slice(&MPR_INDEX_VERSION.to_le_bytes());
+        data.extend_from_slice(&(packages_data.len() as u64).to_le_bytes());
+        data.extend_from_slice(&packages_data);
+        data.extend_from_slice(&indexes_data);
+
+        Ok(data)
+    }
+
+    /// Write this cache out as the binary MPR cache, which every other command
+    /// reads from.
+    pub fn write_index(&self) -> io::Result<()> {
+        let data = self.encode_index()?;
+
+        // Write to a temporary file first, so other commands never see a partially
+        // written cache.
+        let index_path = Self::index_path(&self.repo);
+        let mut tmp_path = index_path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        fs::write(&tmp_path, data)?;
+        fs::rename(&tmp_path, index_path)
+    }
+
+    fn new(repo: &MprRepository) -> Self {
+        if let Some(cache) = Self::from_index(repo) {
+            return cache;
+        }
+
+        // Fall back to the cache archive if the binary cache hasn't been created
+        // yet, such as when it was made by an older version of Mist.
+        let mut cache_file_path = repo.cache_dir();
         cache_file_path.push("cache.gz");
 
         match fs::read(cache_file_path.clone()) {
-            Ok(file) => match Self::validate_data(&file) {
+            Ok(file) => match Self::validate_data(repo, &file) {
                 Ok(cache) => cache,
                 Err(_) => {
                     message::error(&format!(
-                        "There was an issue parsing the cache archive. Try running '{}'.\n",
+                        "There was an issue parsing the cache archive for repository '{}'. Try running '{}'.\n",
+                        repo.name.bold().green(),
                         "mist update".bold().green()
                     ));
                     quit::with_code(exitcode::UNAVAILABLE);
@@ -207,7 +408,8 @@ impl MprCache {
             },
             Err(err) => {
                 message::error(&format!(
-                    "There was an issue reading the cache archive. Try running '{}' [{}].\n",
+                    "There was an issue reading the cache archive for repository '{}'. Try running '{}' [{}].\n",
+                    repo.name.bold().green(),
                     "mist update".bold().green(),
                     err.to_string().bold()
                 ));
@@ -217,10 +419,388 @@ impl MprCache {
     }
 
     pub fn packages(&self) -> &HashMap<String, MprPackage> {
-        &self.packages
+        self.packages
+            .get_or_init(|| match bincode::deserialize(&self.packages_data) {
+                Ok(packages) => packages,
+                Err(_) => {
+                    message::error(&format!(
+                        "There was an issue parsing the cache archive for repository '{}'. Try running '{}'.\n",
+                        self.repo.name.bold().green(),
+                        "mist update".bold().green()
+                    ));
+                    quit::with_code(exitcode::UNAVAILABLE);
+                }
+            })
     }
 }
 
+/// The MPR packages from every configured repository.
+///
+/// Package bases are always taken from a single repository as a whole. When a
+/// package base is available from more than one repository, the one from the
+/// highest priority repository is used, unless another repository was picked
+/// for it via [`MprCache::select_repos`].
+pub struct MprCache {
+    /// The cache of each repository, with the highest priority ones first.
+    repos: Vec<MprRepoCache>,
+    /// The repository picked for each package base that shouldn't come from
+    /// the highest priority one.
+    selected: HashMap<String, String>,
+    /// The packages and indexes of every repository combined. These only get
+    /// built when there's more than one repository to combine.
+    packages: OnceLock<HashMap<String, MprPackage>>,
+    indexes: OnceLock<MprIndexes>,
+    /// The package bases the user has voted for on the MPR, if they've been
+    /// fetched.
+    voted: Option<HashSet<String>>,
+}
+
+impl MprCache {
+    pub fn new() -> Self {
+        let repos = CONFIG
+            .repositories(CONFIG.mpr_url())
+            .iter()
+            .map(MprRepoCache::new)
+            .collect();
+
+        Self {
+            repos,
+            selected: HashMap::new(),
+            packages: OnceLock::new(),
+            indexes: OnceLock::new(),
+            voted: None,
+        }
+    }
+
+    /// Get the cache of the only repository, when there's nothing to combine it
+    /// with.
+    fn single_repo(&self) -> Option<&MprRepoCache> {
+        match self.repos.as_slice() {
+            [repo] if self.selected.is_empty() => Some(repo),
+            _ => None,
+        }
+    }
+
+    pub fn packages(&self) -> &HashMap<String, MprPackage> {
+        if let Some(repo) = self.single_repo() {
+            return repo.packages();
+        }
+
+        self.packages.get_or_init(|| {
+            // Pick the repository of each package base first, so that the packages of
+            // package bases with the same name in different repositories never get
+            // mixed together. Going from the lowest priority repository to the
+            // highest, package bases from higher priority ones replace those from
+            // lower ones.
+            let mut pkgbase_repos: HashMap<&str, usize> = HashMap::new();
+
+            for (index, repo) in self.repos.iter().enumerate().rev() {
+                for pkgbase in repo.indexes.pkgbases.keys() {
+                    if self
+                        .selected
+                        .get(pkgbase)
+                        .is_none_or(|name| *name == repo.repo.name)
+                    {
+                        pkgbase_repos.insert(pkgbase, index);
+                    }
+                }
+            }
+
+            // Then add the packages of each (repository, package base) pair. When a
+            // pkgname is built by package bases with different names, the one from the
+            // higher priority repository wins, and picked package bases win over
+            // everything else.
+            let mut pkgbases: Vec<(&str, usize)> = pkgbase_repos.into_iter().collect();
+            pkgbases.sort_by_key(|(pkgbase, index)| {
+                (self.selected.contains_key(*pkgbase), Reverse(*index))
+            });
+
+            let mut packages = HashMap::new();
+
+            for (pkgbase, index) in pkgbases {
+                let repo = &self.repos[index];
+
+                for pkgname in &repo.indexes.pkgbases[pkgbase] {
+                    packages.insert(pkgname.clone(), repo.packages()[pkgname].clone());
+                }
+            }
+
+            packages
+        })
+    }
+
+    fn indexes(&self) -> &MprIndexes {
+        if let Some(repo) = self.single_repo() {
+            return &repo.indexes;
+        }
+
+        self.indexes
+            .get_or_init(|| MprIndexes::new(self.packages()))
+    }
+
+    /// Pick the repositories of packages passed as `reponame/pkgname`,
+    /// returning the pkgnames without their repositories. The package's whole
+    /// package base comes from the picked repository.
+    pub fn select_repos<'a>(&mut self, pkglist: &[&'a str]) -> Vec<&'a str> {
+        let mut pkgnames = vec![];
+        let mut unfindable = false;
+
+        for pkg in pkglist {
+            let Some((repo_name, pkgname)) = pkg.split_once('/') else {
+                pkgnames.push(*pkg);
+                continue;
+            };
+
+            let Some(repo) = self.repos.iter().find(|repo| repo.repo.name == repo_name) else {
+                message::error(&format!(
+                    "Repository '{}' doesn't exist.\n",
+                    repo_name.green().bold()
+                ));
+                unfindable = true;
+                continue;
+            };
+
+            let Some(pkgbase) = repo.indexes.pkgname_pkgbases.get(pkgname) else {
+                message::error(&format!(
+                    "Unable to find package '{}' in repository '{}'.\n",
+                    pkgname.green().bold(),
+                    repo_name.green().bold()
+                ));
+                unfindable = true;
+                continue;
+            };
+
+            self.selected.insert(pkgbase.clone(), repo_name.to_owned());
+            pkgnames.push(pkgname);
+        }
+
+        if unfindable {
+            quit::with_code(exitcode::USAGE);
+        }
+
+        // The combined packages need to be built again to use the picked
+        // repositories.
+        self.packages = OnceLock::new();
+        self.indexes = OnceLock::new();
+        pkgnames
+    }
+
+    /// Get the directory containing the placeholder '.deb' of each package,
+    /// from the repository it's taken from. These are created in
+    /// [`crate::update::update`].
+    pub fn placeholder_dirs(&self) -> HashMap<String, PathBuf> {
+        let mut dirs = HashMap::new();
+
+        for repo in &self.repos {
+            let mut deb_dir = repo.repo.cache_dir();
+            deb_dir.push("deb-pkgs");
+
+            for entry in fs::read_dir(&deb_dir).into_iter().flatten().flatten() {
+                let filename = entry.file_name().into_string().unwrap();
+
+                let Some(pkgname) = filename.strip_suffix(".deb") else {
+                    continue;
+                };
+
+                let from_repo = self.single_repo().is_some()
+                    || self
+                        .packages()
+                        .get(pkgname)
+                        .is_some_and(|pkg| pkg.repo == repo.repo.name);
+
+                if from_repo {
+                    dirs.insert(pkgname.to_owned(), deb_dir.clone());
+                }
+            }
+        }
+
+        dirs
+    }
+
+    /// Get the repository a package base is taken from.
+    fn pkgbase_repo(&self, pkgbase: &str) -> &str {
+        // Every package of a package base comes from the same repository, so any
+        // of them can be used to find it.
+        self.pkgbase_pkgnames(pkgbase)
+            .first()
+            .and_then(|pkgname| self.packages().get(pkgname))
+            .map_or(MPR_REPO_NAME, |pkg| pkg.repo.as_str())
+    }
+
+    /// Get the Git URL of a package base, from the repository it's taken from.
+    /// `mpr_url` is used for packages from the MPR itself.
+    pub fn pkgbase_url(&self, pkgbase: &str, mpr_url: &str) -> String {
+        let repo_name = self.pkgbase_repo(pkgbase);
+
+        let repo_url = match self.repos.iter().find(|repo| repo.repo.name == repo_name) {
+            Some(repo) if repo_name != MPR_REPO_NAME => repo.repo.url.as_str(),
+            _ => mpr_url,
+        };
+
+        format!("{}/{}", repo_url, pkgbase)
+    }
+
+    /// Set the package bases the user has voted for on the MPR.
+    pub fn set_voted(&mut self, pkgbases: HashSet<String>) {
+        self.voted = Some(pkgbases);
+    }
+
+    /// See if the user has voted for the package base of a package, if their
+    /// votes have been fetched. Votes only exist for packages from the MPR
+    /// itself.
+    pub fn has_voted(&self, pkgname: &str) -> Option<bool> {
+        let voted = self.voted.as_ref()?;
+        let pkg = self.packages().get(pkgname)?;
+
+        (pkg.repo == MPR_REPO_NAME).then(|| voted.contains(&pkg.pkgbase))
+    }
+
+    /// Abort unless a pkgbase exists, pointing the user at the pkgbase that
+    /// builds it if it's a pkgname instead.
+    pub fn ensure_pkgbase(&self, pkgbase: &str) {
+        if self.has_pkgbase(pkgbase) {
+            return;
+        }
+
+        message::error(&format!(
+            "Package base '{}' doesn't exist on the MPR.\n",
+            pkgbase.bold().green()
+        ));
+
+        if let Some(real_pkgbase) = self.pkgbase(pkgbase) {
+            message::error(&format!(
+                "Package base '{}' builds '{}' though, which is probably what you want instead.\n",
+                real_pkgbase.bold().green(),
+                pkgbase.bold().green()
+            ));
+        }
+
+        quit::with_code(exitcode::USAGE);
+    }
+
+    /// Get the pkgbase that builds a pkgname.
+    pub fn pkgbase(&self, pkgname: &str) -> Option<&String> {
+        self.indexes().pkgname_pkgbases.get(pkgname)
+    }
+
+    /// Check if a pkgname exists, without needing to load every package.
+    pub fn has_pkgname(&self, pkgname: &str) -> bool {
+        self.indexes().pkgname_pkgbases.contains_key(pkgname)
+    }
+
+    /// Check if a pkgbase exists.
+    pub fn has_pkgbase(&self, pkgbase: &str) -> bool {
+        self.indexes().pkgbases.contains_key(pkgbase)
+    }
+
+    /// Get the pkgnames built by a pkgbase.
+    pub fn pkgbase_pkgnames(&self, pkgbase: &str) -> &[String] {
+        self.indexes()
+            .pkgbases
+            .get(pkgbase)
+            .map(|pkgnames| pkgnames.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Get the pkgnames that provide a package.
+    pub fn providers(&self, pkgname: &str) -> &[String] {
+        self.indexes()
+            .provides
+            .get(pkgname)
+            .map(|pkgnames| pkgnames.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Get the pkgnames maintained by a user.
+    pub fn maintainer_pkgnames(&self, maintainer: &str) -> &[String] {
+        self.indexes()
+            .maintainers
+            .get(maintainer)
+            .map(|pkgnames| pkgnames.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+/// The directory APT downloads package archives to.
+const APT_ARCHIVES_DIR: &str = "/var/cache/apt/archives";
+
+/// Options that control how [`Cache::commit`] runs a transaction.
+pub struct CommitOptions {
+    /// Automatically answer prompts instead of asking the user.
+    pub assume_yes: bool,
+    /// Offer to review MPR packages' files before building them.
+    pub review: bool,
+    /// Keep the build dependencies of MPR packages installed after building
+    /// them, instead of removing any that nothing else needs.
+    pub keep_build_deps: bool,
+    /// Show what the transaction would do, without making any changes.
+    pub simulate: bool,
+    /// Show the simulated transaction as JSON.
+    pub json: bool,
+    /// Copy built MPR packages into this directory instead of installing
+    /// them.
+    pub output_dir: Option<PathBuf>,
+    /// Use Git checkouts and downloaded APT archives as they are, without
+    /// going online.
+    pub offline: bool,
+}
+
+impl CommitOptions {
+    /// Get the options from a command's arguments, falling back to the
+    /// user's configuration.
+    pub fn from_args(args: &clap::ArgMatches) -> Self {
+        let assume_yes = args.is_present("yes") || CONFIG.assume_yes.unwrap_or(false);
+        // Answering prompts automatically doesn't skip review, which is only turned off
+        // by '--no-review' or the 'review' config key.
+        let review = !args.is_present("no-review") && CONFIG.review.unwrap_or(true);
+
+        Self {
+            assume_yes,
+            review,
+            keep_build_deps: args.is_present("keep-build-deps")
+                || CONFIG.keep_build_deps.unwrap_or(false),
+            simulate: args.is_present("simulate"),
+            json: args
+                .get_one::<String>("output")
+                .is_some_and(|output| output == "json"),
+            output_dir: None,
+            offline: args.is_present("offline"),
+        }
+    }
+}
+
+/// The APT packages changed by a transaction.
+#[derive(Default, Serialize)]
+struct AptChanges {
+    install: Vec<String>,
+    remove: Vec<String>,
+    purge: Vec<String>,
+    upgrade: Vec<String>,
+    downgrade: Vec<String>,
+}
+
+/// The full plan for a transaction, as shown by `--simulate --output json`.
+#[derive(Serialize)]
+struct TransactionPlan<'a> {
+    apt: &'a AptChanges,
+    /// The MPR packages to install, grouped in the order they get installed.
+    mpr_pkgnames: &'a Vec<Vec<String>>,
+    /// The MPR package bases to build, grouped in the order they get built.
+    mpr_build_groups: &'a Vec<Vec<String>>,
+    /// The MPR package bases to clone.
+    mpr_clone: &'a Vec<&'a str>,
+    /// The MPR package bases that are trusted, and so won't be reviewed.
+    mpr_trusted: &'a Vec<&'a str>,
+    /// The build dependencies removed after each group in `mpr_build_groups`
+    /// is built, along with any of their own dependencies that nothing needs
+    /// anymore.
+    mpr_build_deps_removed: &'a Vec<Vec<String>>,
+    /// The APT packages whose archives haven't been downloaded, and so can't
+    /// be installed while offline.
+    apt_missing_archives: &'a Vec<String>,
+    held_back: &'a [String],
+}
+
 /////////////////////////////////////////////
 // Stuff to handled shared APT/MPR caches. //
 /////////////////////////////////////////////
@@ -272,41 +852,193 @@ impl Cache {
         &self.mpr_cache
     }
 
+    /// Get the APT packages marked for installation whose archives haven't
+    /// been downloaded to [`APT_ARCHIVES_DIR`].
+    fn missing_archives(&self) -> Vec<String> {
+        let mut missing = vec![];
+
+        for pkg in self.apt_cache().get_changes(false) {
+            if !(pkg.marked_install()
+                || pkg.marked_upgrade()
+                || pkg.marked_downgrade()
+                || pkg.marked_reinstall())
+            {
+                continue;
+            }
+
+            // APT escapes colons (i.e. from epochs) in the names of the archives it
+            // downloads.
+            let version = pkg.candidate().unwrap();
+            let filename = format!(
+                "{}_{}_{}.deb",
+                pkg.name(),
+                version.version().replace(':', "%3a"),
+                version.arch()
+            );
+
+            if !Path::new(APT_ARCHIVES_DIR).join(filename).exists() {
+                missing.push(pkg.name());
+            }
+        }
+
+        missing
+    }
+
     /// Run a transaction.
-    /// `mpr_pkgs` is the list of MPR packages to install.
-    pub fn commit(&self, mpr_pkgs: &Vec<Vec<String>>, mpr_url: &str) {
-        let mut to_install: Vec<String> = Vec::new();
-        let mut to_remove: Vec<String> = Vec::new();
-        let mut to_purge: Vec<String> = Vec::new();
-        let mut to_upgrade: Vec<String> = Vec::new();
-        let mut to_downgrade: Vec<String> = Vec::new();
+    /// `mpr_pkgs` is the list of MPR packages to install, and `held_back` is
+    /// the list of packages that had an upgrade available but were held or
+    /// pinned.
+    pub fn commit(
+        &self,
+        mpr_pkgs: &Vec<Vec<String>>,
+        mpr_url: &str,
+        options: &CommitOptions,
+        held_back: &[String],
+    ) {
+        // Get the APT changes.
+        let mut apt_changes = AptChanges::default();
 
-        // Report APT packages.
         for pkg in Self::get_nonvirtual_packages(&self.apt_cache, &PackageSort::default()) {
             let pkgname = pkg.name();
-            let apt_string = format!("{}{}", "apt/".to_string().green(), &pkgname);
 
             if pkg.marked_install() {
-                to_install.push(apt_string);
+                apt_changes.install.push(pkgname);
             } else if pkg.marked_delete() {
-                to_remove.push(apt_string);
+                apt_changes.remove.push(pkgname);
             } else if pkg.marked_purge() {
-                to_purge.push(apt_string);
+                apt_changes.purge.push(pkgname);
             } else if pkg.marked_upgrade() {
-                to_upgrade.push(apt_string);
+                apt_changes.upgrade.push(pkgname);
             } else if pkg.marked_downgrade() {
-                to_downgrade.push(apt_string);
+                apt_changes.downgrade.push(pkgname);
+            }
+        }
+
+        // Nothing can be downloaded while offline, so every APT package needs to
+        // already be in the archive cache. When simulating, the missing ones are
+        // shown in the plan instead.
+        let missing_archives = if options.offline {
+            self.missing_archives()
+        } else {
+            vec![]
+        };
+
+        if !options.simulate && !missing_archives.is_empty() {
+            message::error(&format!(
+                "The following packages haven't been downloaded, and so can't be installed while offline: {}\n",
+                missing_archives.join(", ").bold().green()
+            ));
+            quit::with_code(exitcode::UNAVAILABLE);
+        }
+
+        // Get the MPR package bases we need to clone and build.
+        //
+        // We should be able to flatten the `mpr_pkgs` list to get this variable, but I
+        // haven't gotten it to work yet. TODO: Make it work, duh.
+        let mut flattened_pkgnames = vec![];
+        let mut flattened_pkgbases = vec![];
+        let mpr_pkgbases = install_util::pkgnames_to_pkgbases(self, mpr_pkgs);
+
+        for vec in mpr_pkgs {
+            for pkg in vec {
+                flattened_pkgnames.push(pkg.as_str());
+            }
+        }
+
+        for vec in &mpr_pkgbases {
+            for pkg in vec {
+                flattened_pkgbases.push(pkg.as_str());
             }
         }
 
-        // Report MPR packages.
+        // Package bases that are trusted in the user's configuration don't get
+        // reviewed.
+        let (trusted_pkgbases, untrusted_pkgbases): (Vec<&str>, Vec<&str>) = if options.review {
+            flattened_pkgbases
+                .iter()
+                .copied()
+                .partition(|pkgbase| review::is_trusted(self.mpr_cache(), pkgbase))
+        } else {
+            (vec![], flattened_pkgbases.clone())
+        };
+
+        // The build dependencies to remove once each group of package bases has been
+        // built.
+        let removable_build_deps = if options.keep_build_deps {
+            vec![vec![]; mpr_pkgbases.len()]
+        } else {
+            self.removable_build_deps(&mpr_pkgbases, &apt_changes.install)
+        };
+
+        if options.simulate && options.json {
+            let plan = TransactionPlan {
+                apt: &apt_changes,
+                mpr_pkgnames: mpr_pkgs,
+                mpr_build_groups: &mpr_pkgbases,
+                mpr_clone: &flattened_pkgbases,
+                mpr_trusted: &trusted_pkgbases,
+                mpr_build_deps_removed: &removable_build_deps,
+                apt_missing_archives: &missing_archives,
+                held_back,
+            };
+
+            println!("{}", serde_json::to_string_pretty(&plan).unwrap());
+            quit::with_code(exitcode::OK);
+        }
+
+        // Report APT packages.
+        let apt_strings = |pkgnames: &Vec<String>| -> Vec<String> {
+            pkgnames
+                .iter()
+                .map(|pkgname| format!("{}{}", "apt/".to_string().green(), pkgname))
+                .collect()
+        };
+        let mut to_install = apt_strings(&apt_changes.install);
+        let to_remove = apt_strings(&apt_changes.remove);
+        let to_purge = apt_strings(&apt_changes.purge);
+        let to_upgrade = apt_strings(&apt_changes.upgrade);
+        let to_downgrade = apt_strings(&apt_changes.downgrade);
+
+        // Report MPR packages, which only get built when building into an output
+        // directory.
+        let mut to_build = vec![];
+
         for pkg in mpr_pkgs.iter().flatten() {
             let mpr_string = format!("{}{}", "mpr/".to_owned().green(), pkg);
-            to_install.push(mpr_string);
+
+            if options.output_dir.is_some() {
+                to_build.push(mpr_string);
+            } else {
+                to_install.push(mpr_string);
+            }
+        }
+
+        if !held_back.is_empty() {
+            let held_back_strings: Vec<String> = held_back
+                .iter()
+                .map(|pkg| {
+                    let (source, pkgname) = pkg.split_once('/').unwrap();
+                    format!("{}{}", format!("{}/", source).green(), pkgname)
+                })
+                .collect();
+
+            println!("{}", "The following packages have been held back:".bold());
+            util::format_apt_pkglist(&held_back_strings);
+            println!();
+        }
+
+        if !trusted_pkgbases.is_empty() {
+            println!(
+                "{}",
+                "The following MPR package bases are trusted, and won't be reviewed:".bold()
+            );
+            util::format_apt_pkglist(&trusted_pkgbases);
+            println!();
         }
 
         // Print out the transaction.
         if to_install.is_empty()
+            && to_build.is_empty()
             && to_remove.is_empty()
             && to_purge.is_empty()
             && to_upgrade.is_empty()
@@ -322,6 +1054,12 @@ impl Cache {
             println!();
         }
 
+        if !to_build.is_empty() {
+            println!("{}", "The following packages will be built:".bold());
+            util::format_apt_pkglist(&to_build);
+            println!();
+        }
+
         if !to_remove.is_empty() {
             println!(
                 "{}",
@@ -408,106 +1146,94 @@ impl Cache {
             format!("- {} to {}", to_downgrade_count, to_downgrade_string).bold()
         );
 
-        print!("{}", "\nWould you like to continue? [Y/n] ".bold());
-        io::stdout().flush().unwrap();
-
-        let mut resp = String::new();
-        io::stdin().read_line(&mut resp).unwrap();
-        resp.pop();
-
-        if !util::is_yes(&resp, true) {
-            println!("{}", "Aborting...".bold());
-            quit::with_code(exitcode::OK);
+        if !to_build.is_empty() {
+            println!(
+                "{}",
+                format!("- {} to {}", to_build.len(), "build".magenta()).bold()
+            );
         }
 
-        println!();
-        // Clone MPR packages.
-        //
-        // We should be able to flatten the `mpr_pkgs` list to get this variable, but I
-        // haven't gotten it to work yet. TODO: Make it work, duh.
-        let mut flattened_pkgnames = vec![];
-        let mut flattened_pkgbases = vec![];
-        let mpr_pkgbases = install_util::pkgnames_to_pkgbases(self, mpr_pkgs);
-
-        for vec in mpr_pkgs {
-            for pkg in vec {
-                flattened_pkgnames.push(pkg.as_str());
-            }
+        if !held_back.is_empty() {
+            println!(
+                "{}",
+                format!("- {} {}", held_back.len(), "held back".yellow()).bold()
+            );
         }
 
-        for vec in &mpr_pkgbases {
-            for pkg in vec {
-                flattened_pkgbases.push(pkg.as_str());
+        // When simulating, show the rest of the plan and stop before anything gets
+        // changed.
+        if options.simulate {
+            if !missing_archives.is_empty() {
+                println!(
+                    "\n{}",
+                    "The following packages haven't been downloaded, and so can't be installed while offline:".bold()
+                );
+                util::format_apt_pkglist(&missing_archives);
             }
-        }
-
-        install_util::clone_mpr_pkgs(&flattened_pkgbases, mpr_url);
 
-        // Review MPR packages.
+            if !mpr_pkgbases.is_empty() {
+                println!(
+                    "\n{}",
+                    "The following MPR package bases will be cloned:".bold()
+                );
+                util::format_apt_pkglist(&flattened_pkgbases);
 
-        // Get the editor to review package files with.
-        let editor = match edit::get_editor() {
-            Ok(editor) => editor.into_os_string().into_string().unwrap(),
-            Err(err) => {
-                message::error(&format!(
-                    "Couldn't find an editor to review package files with. [{}]\n",
-                    err
-                ));
-
-                quit::with_code(exitcode::UNAVAILABLE);
-            }
-        };
+                println!(
+                    "\n{}",
+                    "MPR packages will be built in the following order:".bold()
+                );
 
-        for pkg in flattened_pkgbases {
-            println!();
+                for (index, pkg_group) in mpr_pkgbases.iter().enumerate() {
+                    println!("  {}. {}", index + 1, pkg_group.join(", "));
+                }
 
-            loop {
-                message::question(&format!(
-                    "Review files for '{}'? [Y/n] ",
-                    pkg.bold().green()
-                ));
-                io::stdout().flush().unwrap();
+                if options.output_dir.is_some() && mpr_pkgbases.len() > 1 {
+                    println!(
+                        "\n{}",
+                        "Every group but the last will be installed so the next ones can be built against it, and removed again afterwards.".bold()
+                    );
+                }
 
-                let mut resp = String::new();
-                io::stdin().read_line(&mut resp).unwrap();
-                resp.pop();
+                let build_deps: Vec<&String> = removable_build_deps.iter().flatten().collect();
 
-                if !util::is_yes(&resp, true) {
-                    break;
+                if !build_deps.is_empty() {
+                    println!(
+                        "\n{}",
+                        "The following build dependencies will be removed once they're no longer needed, along with any of their dependencies that nothing else needs:".bold()
+                    );
+                    util::format_apt_pkglist(&build_deps);
                 }
+            }
 
-                let mut cache_dir = util::xdg::get_cache_dir();
-                cache_dir.push("git-pkg");
-                cache_dir.push(pkg);
-
-                let files = {
-                    let mut files = vec![];
+            quit::with_code(exitcode::OK);
+        }
 
-                    let mut cmd = util::sudo::run_as_normal_user("git");
-                    cmd.args(["ls-tree", "master", "--name-only"]);
-                    let output = cmd.output().unwrap();
-                    util::check_exit_status(&cmd, &output.status);
+        if !options.assume_yes {
+            print!("{}", "\nWould you like to continue? [Y/n] ".bold());
+            io::stdout().flush().unwrap();
 
-                    let string = std::str::from_utf8(&output.stdout).unwrap();
+            let mut resp = String::new();
+            io::stdin().read_line(&mut resp).unwrap();
+            resp.pop();
 
-                    for file in string.lines() {
-                        // There's no point in having the user review the '.SRCINFO' file.
-                        if file == ".SRCINFO" {
-                            continue;
-                        }
+            if !util::is_yes(&resp, true) {
+                println!("{}", "Aborting...".bold());
+                quit::with_code(exitcode::OK);
+            }
+        }
 
-                        files.push(file.to_string());
-                    }
+        println!();
 
-                    files
-                };
+        // Record what this transaction changes in the history log.
+        let mut history = TransactionRecorder::new();
+        let package_changes = history::get_package_changes(self.apt_cache());
 
-                let mut cmd = util::sudo::run_as_normal_user(&editor);
-                cmd.args(files);
+        // Clone MPR packages.
+        install_util::clone_mpr_pkgs(self, &flattened_pkgbases, mpr_url, options.offline);
 
-                let status = cmd.spawn().unwrap().wait().unwrap();
-                util::check_exit_status(&cmd, &status)
-            }
+        // Review MPR packages, unless the user has turned reviews off.
+        if options.review {
+            review::review_pkgbases(&untrusted_pkgbases);
         }
 
         // Install APT packages.
@@ -523,6 +1249,8 @@ impl Cache {
             quit::with_code(exitcode::UNAVAILABLE);
         }
 
+        history.add_changes(package_changes);
+
         // If we're not installing any MPR packages, go ahead and quit.
         if mpr_pkgs.is_empty() {
             quit::with_code(exitcode::OK);
@@ -533,113 +1261,404 @@ impl Cache {
         let mut cache_dir = util::xdg::get_cache_dir();
         cache_dir.push("git-pkg");
 
-        for pkg_group in mpr_pkgbases {
+        // Built packages also get published to the local repository, if one is set
+        // up.
+        let local_repo = Repo::from_config();
+
+        // When building into an output directory, the MPR packages that only got
+        // installed so later package bases could be built against them.
+        let mut temp_installed = vec![];
+        // Every package this transaction installed, out of which the ones nothing
+        // needs anymore get removed along with build dependencies.
+        let mut installed = apt_changes.install.clone();
+
+        for (index, pkg_group) in mpr_pkgbases.iter().enumerate() {
+            let is_last_group = index + 1 == mpr_pkgbases.len();
             let mut debs = vec![];
             // The list of packages to install; A Vector containing pkgname/version pairs.
             let mut install_list: Vec<[String; 2]> = vec![];
+            let mut mpr_builds = vec![];
 
             for pkg in pkg_group {
                 let mut git_dir = cache_dir.clone();
                 git_dir.push(pkg.clone());
-                env::set_current_dir(&git_dir).unwrap();
-
-                // See this package has a control field value of 'MPR-Package'. If it does,
-                // don't add it to our arg list. TODO: We need to add this key
-                // to makedeb's .SRCINFO files.
-                let mpr_package_field = {
-                    let mut cmd = util::sudo::run_as_normal_user("bash");
-                    cmd.arg("-c");
-                    cmd.arg("source PKGBUILD; printf '%s\n' \"${control_fields[@]}\" | grep -q '^MPR-Package:'");
-                    cmd.output().unwrap().status.success()
-                };
 
-                let mut cmd = util::sudo::run_as_normal_user("makedeb");
+                let build = install_util::build_pkgbase(pkg, &git_dir);
+                let mut built_pkgs = vec![];
 
-                if !mpr_package_field {
-                    cmd.arg("-H");
-                    cmd.arg("MPR-Package: yes");
+                if let Some(output_dir) = &options.output_dir {
+                    install_util::copy_debs(&build, output_dir);
                 }
 
-                message::info(&format!("Running makedeb for '{}'...\n", pkg.green()));
-                if !cmd.spawn().unwrap().wait().unwrap().success() {
-                    message::error("Failed to run makedeb.\n");
-                    quit::with_code(exitcode::UNAVAILABLE);
+                if let Some(repo) = &local_repo {
+                    let deb_paths: Vec<PathBuf> = build
+                        .debs
+                        .iter()
+                        .map(|deb| build.dir.join(&deb.filename))
+                        .collect();
+                    repo.add(&deb_paths);
                 }
 
-                // Get the list of '.deb' files that were built.
-                for dir in fs::read_dir("./pkg").unwrap() {
-                    let mut path = dir.unwrap().path();
-                    path.push("DEBIAN");
-                    path.push("control");
-                    let control_file =
-                        TagSection::new(&fs::read_to_string(&path).unwrap()).unwrap();
-
+                for deb in build.debs {
                     // Only add this deb for installation if the user asked for it to be installed.
-                    let pkgname = control_file.get("Package").unwrap();
-                    let version = control_file.get("Version").unwrap();
-                    let arch = control_file.get("Architecture").unwrap();
-
-                    if flattened_pkgnames.contains(&pkgname.as_str()) {
-                        debs.push(format!(
-                            "{}/{}_{}_{}.deb",
-                            git_dir.display(),
-                            pkgname,
-                            version,
-                            arch
-                        ));
+                    if flattened_pkgnames.contains(&deb.pkgname.as_str()) {
+                        debs.push(build.dir.join(&deb.filename).display().to_string());
                     }
 
-                    install_list.push([
-                        pkgname.to_string(),
-                        control_file.get("Version").unwrap().to_string(),
-                    ]);
+                    install_list.push([deb.pkgname.clone(), deb.version.clone()]);
+                    built_pkgs.push(BuiltPackage {
+                        pkgname: deb.pkgname,
+                        version: deb.version,
+                    });
                 }
 
+                mpr_builds.push(MprBuild {
+                    pkgbase: pkg.clone(),
+                    commit: build.commit,
+                    packages: built_pkgs,
+                });
+
                 env::set_current_dir(&current_dir).unwrap();
             }
 
-            // Convert the debs into the format required by the
-            // [`rust_apt::cache::Cache::debs`] initializer.
-            let mut debs_as_str = vec![];
-            for deb in &debs {
-                debs_as_str.push(deb.as_str());
+            // When only building packages, they don't get installed, besides the
+            // ones later package bases could need to be built.
+            if options.output_dir.is_none() || !is_last_group {
+                // Convert the debs into the format required by the
+                // [`rust_apt::cache::Cache::debs`] initializer.
+                let mut debs_as_str = vec![];
+                for deb in &debs {
+                    debs_as_str.push(deb.as_str());
+                }
+
+                // Install the packages.
+                let deb_cache = AptCache::debs(&debs_as_str).unwrap();
+
+                for pkg in &install_list {
+                    let cache_pkg = deb_cache.get(&pkg[0]).unwrap();
+                    let version = cache_pkg.get_version(&pkg[1]).unwrap();
+                    version.set_candidate();
+                    assert!(cache_pkg.mark_install(false, true));
+                    cache_pkg.protect();
+                }
+
+                if let Err(err) = deb_cache.resolve(true) {
+                    util::handle_errors(&err);
+                    quit::with_code(exitcode::UNAVAILABLE);
+                }
+
+                let package_changes = history::get_package_changes(&deb_cache);
+
+                if options.output_dir.is_some() {
+                    for pkg in Self::get_nonvirtual_packages(&deb_cache, &PackageSort::default()) {
+                        if pkg.marked_install() {
+                            installed.push(pkg.name());
+                        }
+                    }
+
+                    for [pkgname, _] in &install_list {
+                        if !self
+                            .apt_cache()
+                            .get(pkgname)
+                            .is_some_and(|pkg| pkg.is_installed())
+                        {
+                            temp_installed.push(pkgname.clone());
+                        }
+                    }
+                }
+
+                if deb_cache.get_archives(&mut updater).is_err() {
+                    message::error("Failed to fetch needed archives\n");
+                    quit::with_code(exitcode::UNAVAILABLE);
+                }
+
+                if let Err(err) = deb_cache.do_install(&mut installer) {
+                    util::handle_errors(&err);
+                    quit::with_code(exitcode::UNAVAILABLE);
+                }
+
+                history.add_changes(package_changes);
+
+                if options.output_dir.is_none() {
+                    history.add_mpr_builds(mpr_builds);
+                }
             }
 
-            // Install the packages.
-            let deb_cache = AptCache::debs(&debs_as_str).unwrap();
+            // Remove the build dependencies this transaction installed for these
+            // packages, as long as later package bases don't need them either. When
+            // only building packages, the MPR packages that got installed to build
+            // later ones aren't needed anymore once the last one has been built.
+            //
+            // Until the last group has been built, nothing installed depends on the
+            // dependencies of later groups yet, so anything else this transaction
+            // installed only gets removed after the last group.
+            if !options.keep_build_deps {
+                let (temp_pkgs, autoremovable): (&[String], &[String]) = if is_last_group {
+                    (temp_installed.as_slice(), installed.as_slice())
+                } else {
+                    (&[], &[])
+                };
 
-            for pkg in &install_list {
-                let cache_pkg = deb_cache.get(&pkg[0]).unwrap();
-                let version = cache_pkg.get_version(&pkg[1]).unwrap();
-                version.set_candidate();
-                assert!(cache_pkg.mark_install(false, true));
-                cache_pkg.protect();
+                Self::remove_build_deps(
+                    &removable_build_deps[index],
+                    temp_pkgs,
+                    autoremovable,
+                    &mut history,
+                    &mut updater,
+                    &mut installer,
+                );
             }
+        }
+    }
 
-            if let Err(err) = deb_cache.resolve(true) {
-                util::handle_errors(&err);
-                quit::with_code(exitcode::UNAVAILABLE);
+    /// Get the names of the build dependencies (`makedepends` and
+    /// `checkdepends`) of the given MPR package bases on this system.
+    fn get_build_deps(&self, pkgbases: &[String]) -> HashSet<String> {
+        let (system_distro, system_arch) = util::get_distro_arch_info();
+        let mut build_deps = HashSet::new();
+
+        for pkgbase in pkgbases {
+            for pkgname in self.mpr_cache().pkgbase_pkgnames(pkgbase) {
+                let pkg = self.mpr_cache().packages().get(pkgname).unwrap();
+
+                for deps in [
+                    pkg.get_system_makedepends(&system_distro, &system_arch),
+                    pkg.get_system_checkdepends(&system_distro, &system_arch),
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    build_deps.extend(query::dependency_names(&deps));
+                }
             }
+        }
 
-            if deb_cache.get_archives(&mut updater).is_err() {
-                message::error("Failed to fetch needed archives\n");
-                quit::with_code(exitcode::UNAVAILABLE);
+        build_deps
+    }
+
+    /// Get the build dependencies that this transaction installs (out of
+    /// `installed`) for each group of package bases in `mpr_pkgbases`, which
+    /// can be removed once that group has been built as long as later groups
+    /// don't need them either.
+    fn removable_build_deps(
+        &self,
+        mpr_pkgbases: &[Vec<String>],
+        installed: &[String],
+    ) -> Vec<Vec<String>> {
+        let mut removable = vec![];
+
+        for (index, pkg_group) in mpr_pkgbases.iter().enumerate() {
+            let later_build_deps = self.get_build_deps(&mpr_pkgbases[index + 1..].concat());
+            let mut build_deps: Vec<String> = self
+                .get_build_deps(pkg_group)
+                .into_iter()
+                .filter(|dep| installed.contains(dep) && !later_build_deps.contains(dep))
+                .collect();
+
+            build_deps.sort();
+            removable.push(build_deps);
+        }
+
+        removable
+    }
+
+    /// Remove any of `build_deps` that nothing else depends on anymore, along
+    /// with `temp_pkgs` (MPR packages that were only installed to build
+    /// others) and any packages out of `autoremovable` that nothing depends on
+    /// anymore.
+    ///
+    /// `autoremovable` should only contain packages this transaction
+    /// installed, and only once every group has been built, as the
+    /// dependencies of groups that haven't been built yet don't have anything
+    /// depending on them.
+    ///
+    /// Build dependencies get installed as automatically installed packages, so
+    /// this is the same as what `apt autoremove` would do for them.
+    fn remove_build_deps(
+        build_deps: &[String],
+        temp_pkgs: &[String],
+        autoremovable: &[String],
+        history: &mut TransactionRecorder,
+        updater: &mut Box<dyn AcquireProgress>,
+        installer: &mut Box<dyn InstallProgress>,
+    ) {
+        let cache = AptCache::new();
+        let mut to_remove = vec![];
+
+        for pkgname in temp_pkgs {
+            if let Some(pkg) = cache.get(pkgname)
+                && pkg.is_installed()
+            {
+                pkg.mark_delete(false);
+                pkg.protect();
+                to_remove.push(pkgname);
             }
+        }
 
-            if let Err(err) = deb_cache.do_install(&mut installer) {
-                util::handle_errors(&err);
-                quit::with_code(exitcode::UNAVAILABLE);
+        for dep in build_deps {
+            if let Some(pkg) = cache.get(dep)
+                && pkg.is_auto_removable()
+            {
+                pkg.mark_delete(false);
+                pkg.protect();
+                to_remove.push(dep);
             }
         }
+
+        // Removing the build dependencies (including those of earlier groups) can
+        // leave the packages they pulled in unneeded too. Marking a package for
+        // removal can in turn make others removable, so keep going until nothing
+        // else can be removed.
+        loop {
+            let mut removed_any = false;
+
+            for pkgname in autoremovable {
+                if to_remove.contains(&pkgname) {
+                    continue;
+                }
+
+                if let Some(pkg) = cache.get(pkgname)
+                    && pkg.is_auto_removable()
+                {
+                    pkg.mark_delete(false);
+                    pkg.protect();
+                    to_remove.push(pkgname);
+                    removed_any = true;
+                }
+            }
+
+            if !removed_any {
+                break;
+            }
+        }
+
+        if to_remove.is_empty() {
+            return;
+        }
+
+        println!(
+            "\n{}",
+            "The following build dependencies are no longer needed and will be removed:".bold()
+        );
+        util::format_apt_pkglist(&to_remove);
+        println!();
+
+        if let Err(err) = cache.resolve(true) {
+            util::handle_errors(&err);
+            quit::with_code(exitcode::UNAVAILABLE);
+        }
+
+        let package_changes = history::get_package_changes(&cache);
+
+        if cache.get_archives(updater).is_err() {
+            message::error("Failed to fetch needed archives\n");
+            quit::with_code(exitcode::UNAVAILABLE);
+        }
+
+        if let Err(err) = cache.do_install(installer) {
+            util::handle_errors(&err);
+            quit::with_code(exitcode::UNAVAILABLE);
+        }
+
+        history.add_changes(package_changes);
     }
 
     // Find the pkgbase of a given MPR package's pkgname.
     pub fn find_pkgbase(&self, pkgname: &str) -> Option<String> {
-        for pkg in self.mpr_cache().packages().values() {
-            if pkg.pkgname == pkgname {
-                return Some(pkg.pkgbase.clone());
-            }
+        self.mpr_cache().pkgbase(pkgname).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo() -> MprRepository {
+        MprRepository {
+            name: "test".to_owned(),
+            url: "https://example.com".to_owned(),
+            priority: 0,
         }
-        None
+    }
+
+    fn repo_cache() -> MprRepoCache {
+        let package = |pkgname: &str, provides: &str| {
+            format!(
+                r#"{{"Name": "{}", "PackageBase": "base", "Version": "1.0-1", "Description": null, "Maintainer": "alice", "NumVotes": 1, "Popularity": 0.5, "OutOfDate": null, "Depends": [], "MakeDepends": [], "CheckDepends": [], "Conflicts": [], "Provides": [{{"Distro": null, "Arch": null, "Packages": ["{}"]}}]}}"#,
+                pkgname, provides
+            )
+        };
+        let json = format!(
+            "[{}, {}]",
+            package("base-bin", "base-virtual>=1.0"),
+            package("base-doc", "base-virtual")
+        );
+
+        MprRepoCache::validate_data(&repo(), json.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn indexes() {
+        let cache = repo_cache();
+
+        assert_eq!(cache.packages()["base-bin"].repo, "test");
+        assert_eq!(cache.indexes.pkgname_pkgbases["base-doc"], "base");
+        assert_eq!(cache.indexes.pkgbases["base"], ["base-bin", "base-doc"]);
+        assert_eq!(cache.indexes.maintainers["alice"], ["base-bin", "base-doc"]);
+        // Version constraints aren't part of the provided package's name.
+        assert_eq!(
+            cache.indexes.provides["base-virtual"],
+            ["base-bin", "base-doc"]
+        );
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let cache = repo_cache();
+        let data = cache.encode_index().unwrap();
+
+        assert_eq!(&data[..4], MPR_INDEX_MAGIC);
+        assert!(MprRepoCache::parse_header(&data).is_some());
+
+        let decoded = MprRepoCache::decode_index(&repo(), data).unwrap();
+        assert!(decoded.packages() == cache.packages());
+        assert_eq!(decoded.indexes.pkgbases["base"], ["base-bin", "base-doc"]);
+    }
+
+    #[test]
+    fn index_header() {
+        let data = repo_cache().encode_index().unwrap();
+        let packages_len = MprRepoCache::parse_header(&data).unwrap();
+        assert!(MPR_INDEX_HEADER_LEN + packages_len < data.len());
+
+        // Only the header is needed to read it.
+        assert_eq!(
+            MprRepoCache::parse_header(&data[..MPR_INDEX_HEADER_LEN]),
+            Some(packages_len)
+        );
+        assert!(MprRepoCache::parse_header(&data[..MPR_INDEX_HEADER_LEN - 1]).is_none());
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        assert!(MprRepoCache::parse_header(&bad_magic).is_none());
+        assert!(MprRepoCache::decode_index(&repo(), bad_magic).is_none());
+
+        // Caches written in another version of the format get rebuilt.
+        let mut other_version = data.clone();
+        other_version[4..8].copy_from_slice(&(MPR_INDEX_VERSION + 1).to_le_bytes());
+        assert!(MprRepoCache::parse_header(&other_version).is_none());
+        assert!(MprRepoCache::decode_index(&repo(), other_version).is_none());
+    }
+
+    #[test]
+    fn index_truncated() {
+        let data = repo_cache().encode_index().unwrap();
+        let packages_len = MprRepoCache::parse_header(&data).unwrap();
+
+        let truncated = data[..MPR_INDEX_HEADER_LEN + packages_len - 1].to_vec();
+        assert!(MprRepoCache::decode_index(&repo(), truncated).is_none());
+
+        let truncated = data[..data.len() - 1].to_vec();
+        assert!(MprRepoCache::decode_index(&repo(), truncated).is_none());
     }
 }
@@ -0,0 +1,235 @@
+use crate::{
+    apt_util::{self, NumSys},
+    cache::MprCache,
+    install_util, message,
+    style::Colorize,
+    util,
+};
+use rust_apt::tagfile;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Get the total size of the files at `path`.
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Remove a file or directory, returning how much space was freed.
+fn remove(path: &Path) -> u64 {
+    let size = disk_usage(path);
+    let result = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => return 0,
+    };
+
+    match result {
+        Ok(()) => size,
+        Err(err) => {
+            message::warning(&format!(
+                "Failed to remove '{}' [{}]\n",
+                path.display().to_string().bold().green(),
+                err.to_string().bold()
+            ));
+            0
+        }
+    }
+}
+
+/// Get the paths of everything in a directory, or nothing if it doesn't exist.
+fn dir_entries(dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Abort if a directory in the user's cache directory is a symbolic link, as it
+/// could point anywhere on the system.
+fn check_not_symlink(path: &Path) {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+        message::error(&format!(
+            "Refusing to clean '{}', as it's a symbolic link.\n",
+            path.display().to_string().bold().green()
+        ));
+        quit::with_code(exitcode::DATAERR);
+    }
+}
+
+/// Get the package bases of the MPR packages installed on this system.
+fn installed_pkgbases() -> HashSet<String> {
+    let mpr_cache = MprCache::new();
+    let dpkg_pkgs =
+        tagfile::parse_tagfile(&fs::read_to_string("/var/lib/dpkg/status").unwrap()).unwrap();
+    let mut pkgbases = HashSet::new();
+
+    for pkg in dpkg_pkgs {
+        let installed = pkg
+            .get("Status")
+            .is_some_and(|status| status.ends_with(" installed"));
+
+        if installed && pkg.get("MPR-Package").is_some() {
+            let pkgname = pkg.get("Package").unwrap();
+
+            // Packages that have since been removed from the MPR were most likely
+            // built from a package base with the same name.
+            let pkgbase = mpr_cache.pkgbase(pkgname).unwrap_or(pkgname);
+            pkgbases.insert(pkgbase.to_owned());
+        }
+    }
+
+    pkgbases
+}
+
+/// Remove the Git checkouts of MPR package bases, keeping those of installed
+/// packages unless `include_installed` is set.
+fn clean_sources(git_pkg_dir: &Path, include_installed: bool) -> u64 {
+    let installed = if include_installed {
+        HashSet::new()
+    } else {
+        installed_pkgbases()
+    };
+    let mut freed = 0;
+    let mut removed = 0;
+    let mut kept = 0;
+
+    for path in dir_entries(git_pkg_dir) {
+        let pkgbase = path.file_name().unwrap().to_string_lossy();
+
+        if installed.contains(pkgbase.as_ref()) {
+            kept += 1;
+            continue;
+        }
+
+        freed += remove(&path);
+        removed += 1;
+    }
+
+    message::info(&format!(
+        "Removed {} Git checkouts of MPR packages ({}).\n",
+        removed.to_string().bold(),
+        apt_util::unit_str(freed, NumSys::Decimal)
+    ));
+
+    if kept != 0 {
+        message::info(&format!(
+            "Kept {} Git checkouts of installed MPR packages. Pass '{}' to remove them too.\n",
+            kept.to_string().bold(),
+            "--all".bold().green()
+        ));
+    }
+
+    freed
+}
+
+/// Remove cached builds, along with the build files left in Git checkouts.
+fn clean_builds(build_cache_dir: &Path, git_pkg_dir: &Path) -> u64 {
+    let mut freed = remove(build_cache_dir);
+
+    for git_dir in dir_entries(git_pkg_dir) {
+        // Only look inside actual checkouts, and not anything a symbolic link
+        // points to.
+        if !fs::symlink_metadata(&git_dir).is_ok_and(|metadata| metadata.is_dir()) {
+            continue;
+        }
+
+        for path in dir_entries(&git_dir) {
+            let filename = path.file_name().unwrap().to_string_lossy();
+
+            if ["src", "pkg"].contains(&filename.as_ref()) || filename.ends_with(".deb") {
+                freed += remove(&path);
+            }
+        }
+    }
+
+    message::info(&format!(
+        "Removed built MPR packages and build files ({}).\n",
+        apt_util::unit_str(freed, NumSys::Decimal)
+    ));
+
+    freed
+}
+
+/// Remove the MPR metadata downloaded by `mist update`, along with the
+/// placeholder packages generated from it.
+fn clean_metadata() -> u64 {
+    let freed = dir_entries(&util::xdg::get_global_cache_dir())
+        .into_iter()
+        .map(|path| remove(&path))
+        .sum();
+
+    message::info(&format!(
+        "Removed MPR metadata ({}). Run '{}' to download it again.\n",
+        apt_util::unit_str(freed, NumSys::Decimal),
+        "mist update".bold().green()
+    ));
+
+    freed
+}
+
+pub fn clean(args: &clap::ArgMatches) {
+    let all = args.is_present("all");
+    let mut builds = all || args.is_present("builds");
+    let mut sources = all || args.is_present("sources");
+    let metadata = all || args.is_present("metadata");
+
+    // Clean up build files and Git checkouts when nothing else was asked for.
+    if !builds && !sources && !metadata {
+        builds = true;
+        sources = true;
+    }
+
+    let cache_dir = util::xdg::get_cache_dir();
+    let git_pkg_dir = cache_dir.join("git-pkg");
+    let build_cache_dir = install_util::get_build_cache_dir();
+    let mut freed = 0;
+
+    // The user's cache directory is under their control, so everything in it
+    // gets removed as them, and none of it gets followed if it's a symbolic link.
+    // Otherwise it could be pointed at files that only root can remove.
+    if sources || builds {
+        for dir in [&cache_dir, &git_pkg_dir, &build_cache_dir] {
+            check_not_symlink(dir);
+        }
+
+        util::sudo::to_normal();
+
+        // Find the installed packages before any metadata gets removed, since it's
+        // needed to find their package bases.
+        if sources {
+            freed += clean_sources(&git_pkg_dir, all);
+        }
+        if builds {
+            freed += clean_builds(&build_cache_dir, &git_pkg_dir);
+        }
+
+        util::sudo::to_root();
+    }
+
+    if metadata {
+        freed += clean_metadata();
+    }
+
+    message::info(&format!(
+        "Freed {} of disk space.\n",
+        apt_util::unit_str(freed, NumSys::Decimal).bold()
+    ));
+}
@@ -8,15 +8,9 @@ pub fn clone(args: &clap::ArgMatches) {
     let pkg: &String = args.get_one("pkg").unwrap();
     let mpr_url: &String = args.get_one("mpr-url").unwrap();
     let cache = Cache::new(AptCache::new(), MprCache::new());
-    let mut pkgbases: Vec<&String> = Vec::new();
-
-    // Get a list of package bases.
-    for pkg in cache.mpr_cache().packages().values() {
-        pkgbases.push(&pkg.pkgbase);
-    }
 
     // Abort if the package base doesn't exist.
-    if !pkgbases.contains(&pkg) {
+    if !cache.mpr_cache().has_pkgbase(pkg) {
         message::error(&format!(
             "Package base '{}' doesn't exist on the MPR.\n",
             pkg
@@ -35,13 +29,20 @@ pub fn clone(args: &clap::ArgMatches) {
                 clap::crate_name!(),
                 pkgbase
             ));
+        } else if !cache.mpr_cache().providers(pkg).is_empty() {
+            // Otherwise, point them at any packages that provide it.
+            message::error(&format!(
+                "'{}' is provided by the following MPR packages though: {}\n",
+                &pkg,
+                cache.mpr_cache().providers(pkg).join(", ")
+            ));
         }
 
         quit::with_code(exitcode::USAGE);
     }
 
     // Clone the package.
-    let pkg_url = format!("{}/{}", mpr_url, pkg);
+    let pkg_url = cache.mpr_cache().pkgbase_url(pkg, mpr_url);
     let mut cmd = util::sudo::run_as_normal_user("git");
     cmd.args(["clone", &pkg_url]);
     let exit_code = cmd.output().unwrap().status;
@@ -1,8 +1,6 @@
-use crate::{cache::MprCache, message, util};
+use crate::{cache::MprCache, list_comments, message, style::Colorize, util};
 use serde::Deserialize;
 use serde_json::json;
-use std::fs::File;
-use std::io::prelude::*;
 
 #[derive(Deserialize)]
 struct CommentResult {
@@ -12,82 +10,74 @@ struct CommentResult {
 pub fn comment(args: &clap::ArgMatches) {
     let pkg: &String = args.get_one("pkg").unwrap();
     let mpr_url: &String = args.get_one("mpr-url").unwrap();
-    let api_token: &String = match args.get_one("token") {
-        Some(token) => token,
-        None => {
-            message::error("No API token was provided.\n");
-            quit::with_code(exitcode::USAGE);
-        }
-    };
+    let api_token = util::get_api_token(args);
 
     // Get a list of packages.
     let mpr_cache = MprCache::new();
-    let mut pkgnames: Vec<&String> = Vec::new();
-
-    for pkg in mpr_cache.packages().values() {
-        pkgnames.push(&pkg.pkgname);
-    }
 
     // Abort if the package base doesn't exist.
-    if !pkgnames.contains(&pkg) {
+    if !mpr_cache.has_pkgname(pkg) {
         message::error(&format!("Package '{}' doesn't exist on the MPR.\n", pkg));
         quit::with_code(exitcode::USAGE);
     }
 
+    let request = util::AuthenticatedRequest::new(api_token, mpr_url);
+
+    // Deleting a comment doesn't need a message, so handle it first.
+    if let Some(id) = args.get_one::<u32>("delete") {
+        check_comment_exists(mpr_url, pkg, *id);
+        request.post_checked(&format!("delete-comment/{}/{}", pkg, id), String::new());
+        message::info(&format!("Succesfully deleted comment {}.\n", id));
+        return;
+    }
+
+    let edit_id = args.get_one::<u32>("edit");
+
+    if let Some(id) = edit_id {
+        check_comment_exists(mpr_url, pkg, *id);
+    }
+
     // Get the message.
     // If no message was supplied, get one from the user.
     let msg: String = match args.get_one::<String>("msg") {
         Some(msg) => (msg).to_owned(),
-        None => {
-            // Get the editor.
-            let editor = match edit::get_editor() {
-                Ok(editor) => editor.into_os_string().into_string().unwrap(),
-                Err(err) => {
-                    message::error(&format!(
-                        "Couldn't find an editor to write a comment with. [{}]\n",
-                        err
-                    ));
-
-                    quit::with_code(exitcode::UNAVAILABLE);
-                }
-            };
-
-            // Generate a temporary file to write the message in.
-            let file = match tempfile::Builder::new().suffix(".md").tempfile_in("/tmp") {
-                Ok(file) => file.path().to_str().unwrap().to_owned(),
-                Err(err) => {
-                    message::error(&format!(
-                        "Failed to create temporary file to write comment in. [{}]\n",
-                        err
-                    ));
-                    quit::with_code(exitcode::UNAVAILABLE);
-                }
-            };
-
-            // Open the file in the editor.
-            message::info(&format!("Opening '{}' in '{}'...\n", &file, editor));
-
-            let mut cmd = util::sudo::run_as_normal_user(&editor);
-            cmd.arg(&file);
-            let status = cmd.spawn().unwrap().wait().unwrap();
-            util::check_exit_status(&cmd, &status);
-
-            // Read the changed file.
-            let mut file_content = String::new();
-            let mut _file = File::open(file).unwrap();
-            _file.read_to_string(&mut file_content).unwrap();
-
-            file_content
-        }
+        None => util::edit_message("a comment"),
     };
 
+    if edit_id.is_some() && msg.trim().is_empty() {
+        message::error(&format!(
+            "A comment can't be edited to be empty. Use '{}' to delete it instead.\n",
+            "--delete".bold().green()
+        ));
+        quit::with_code(exitcode::USAGE);
+    }
+
     // Upload the message!
     let body = json!({ "msg": msg }).to_string();
 
-    let request = util::AuthenticatedRequest::new(api_token, mpr_url);
+    if let Some(id) = edit_id {
+        request.post_checked(&format!("edit-comment/{}/{}", pkg, id), body);
+        message::info(&format!("Succesfully edited comment {}.\n", id));
+        return;
+    }
+
     let resp_text = request.post(&format!("comment/{}", pkg), body);
 
     // Parse the message.
     let json = serde_json::from_str::<CommentResult>(&resp_text).unwrap();
     message::info(&format!("Succesfully posted comment. [{}]\n", json.link));
 }
+
+/// Abort if the given comment doesn't exist on a package.
+fn check_comment_exists(mpr_url: &str, pkg: &str, id: u32) {
+    let comments = list_comments::get_comments(mpr_url, pkg);
+
+    if !comments.iter().any(|comment| comment.id == id) {
+        message::error(&format!(
+            "Comment {} doesn't exist on package '{}'.\n",
+            id.to_string().bold().green(),
+            pkg.bold().green()
+        ));
+        quit::with_code(exitcode::USAGE);
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{message, style::Colorize, util};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+use users::os::unix::UserExt;
+
+/// The system-wide configuration file.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/mist/config.toml";
+
+/// The MPR instance to use when none is configured.
+const DEFAULT_MPR_URL: &str = "https://mpr.makedeb.org";
+
+/// The name of the repository for the MPR itself, which is always available.
+pub const MPR_REPO_NAME: &str = "mpr";
+
+lazy_static! {
+    /// The configuration for this run, with the user's configuration file
+    /// layered over the system-wide one.
+    pub static ref CONFIG: Config = Config::load();
+}
+
+/// An MPR-compatible instance to get packages from.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MprRepository {
+    /// The name the repository is referred to by, i.e. in `reponame/pkg`.
+    pub name: String,
+    pub url: String,
+    /// Packages are taken from the repository with the highest priority when
+    /// they're available from more than one.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+impl MprRepository {
+    /// Get the directory the repository's metadata and placeholder packages are
+    /// stored in.
+    pub fn cache_dir(&self) -> PathBuf {
+        let mut cache_dir = util::xdg::get_global_cache_dir();
+        cache_dir.push("repos");
+        cache_dir.push(&self.name);
+        cache_dir
+    }
+}
+
+/// Settings read from Mist's configuration files. Every field is optional, so
+/// that a user's configuration file only needs to contain the values it wants
+/// to change from the system-wide one.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// The URL to access the MPR from.
+    pub mpr_url: Option<String>,
+    /// When to send output to a pager.
+    pub paging: Option<String>,
+    /// Whether to automatically answer prompts during transactions.
+    pub assume_yes: Option<bool>,
+    /// Whether to offer to review MPR packages' files before building them.
+    pub review: Option<bool>,
+    /// Whether to keep the build dependencies of MPR packages after building
+    /// them.
+    pub keep_build_deps: Option<bool>,
+    /// The editor to review package files and write comments with.
+    pub editor: Option<String>,
+    /// The source to install a package from when it's available from both APT
+    /// and the MPR.
+    pub prefer: Option<String>,
+    /// Extra arguments to pass to `makedeb` when building MPR packages.
+    pub makedeb_args: Option<Vec<String>>,
+    /// MPR maintainers whose packages are trusted, and so aren't reviewed.
+    pub trusted_maintainers: Option<Vec<String>>,
+    /// MPR package bases that are trusted, and so aren't reviewed.
+    pub trusted_pkgbases: Option<Vec<String>>,
+    /// The directory of the local APT repository that built MPR packages get
+    /// published to. This and `repo_signing_key` are only read from the
+    /// system-wide configuration file.
+    pub repo_dir: Option<String>,
+    /// The GPG key to sign the local APT repository with.
+    pub repo_signing_key: Option<String>,
+    /// MPR-compatible instances to get packages from, besides the MPR itself.
+    pub repositories: Option<Vec<MprRepository>>,
+}
+
+impl Config {
+    /// Read a configuration file, returning an empty configuration if it
+    /// doesn't exist.
+    fn from_file(path: &Path) -> Self {
+        let path_string = path.display().to_string();
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Self::default(),
+            Err(err) => {
+                message::error(&format!(
+                    "Failed to read config file '{}' [{}]\n",
+                    path_string.bold().green(),
+                    err.to_string().bold()
+                ));
+                quit::with_code(exitcode::CONFIG);
+            }
+        };
+
+        match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(err) => {
+                message::error(&format!(
+                    "Failed to parse config file '{}' [{}]\n",
+                    path_string.bold().green(),
+                    err.to_string().bold()
+                ));
+                quit::with_code(exitcode::CONFIG);
+            }
+        }
+    }
+
+    /// Layer `other` over this configuration, with any values set in `other`
+    /// taking precedence.
+    fn merge(self, other: Self) -> Self {
+        Self {
+            mpr_url: other.mpr_url.or(self.mpr_url),
+            paging: other.paging.or(self.paging),
+            assume_yes: other.assume_yes.or(self.assume_yes),
+            review: other.review.or(self.review),
+            keep_build_deps: other.keep_build_deps.or(self.keep_build_deps),
+            editor: other.editor.or(self.editor),
+            prefer: other.prefer.or(self.prefer),
+            makedeb_args: other.makedeb_args.or(self.makedeb_args),
+            trusted_maintainers: other.trusted_maintainers.or(self.trusted_maintainers),
+            trusted_pkgbases: other.trusted_pkgbases.or(self.trusted_pkgbases),
+            // The local repository gets written to as root, so it can only be set up in
+            // the system-wide configuration file.
+            repo_dir: self.repo_dir,
+            repo_signing_key: self.repo_signing_key,
+            // Repositories are added to the ones from the system-wide configuration
+            // file instead of replacing them.
+            repositories: match (self.repositories, other.repositories) {
+                (Some(mut repos), Some(other_repos)) => {
+                    repos.extend(other_repos);
+                    Some(repos)
+                }
+                (repos, other_repos) => other_repos.or(repos),
+            },
+        }
+    }
+
+    /// Make sure any values that only accept a fixed set of options are valid.
+    fn validate(&self) {
+        let checks = [
+            (
+                "paging",
+                &self.paging,
+                ["auto", "always", "never"].as_slice(),
+            ),
+            ("prefer", &self.prefer, ["apt", "mpr"].as_slice()),
+        ];
+
+        for (key, value, allowed) in checks {
+            if let Some(value) = value
+                && !allowed.contains(&value.as_str())
+            {
+                message::error(&format!(
+                    "Invalid value '{}' for config key '{}'. Valid values are: {}\n",
+                    value.bold().green(),
+                    key.bold(),
+                    allowed.join(", ")
+                ));
+                quit::with_code(exitcode::CONFIG);
+            }
+        }
+
+        // Repository names end up in paths and in `reponame/pkg` arguments, and need
+        // to tell repositories apart. Metadata is stored by name and shared by every
+        // user, so this also keeps users' own repositories from reusing the names of
+        // those in the system-wide configuration file.
+        let mut repo_names = vec![MPR_REPO_NAME];
+
+        for repo in self.repositories.iter().flatten() {
+            let invalid = repo.name.is_empty()
+                || repo.name.starts_with('.')
+                || repo.name.contains('/')
+                || repo_names.contains(&repo.name.as_str());
+
+            if invalid {
+                message::error(&format!(
+                    "Invalid repository name '{}' in config key '{}'. Names need to be unique across the system-wide and user configuration files, can't start with '.' or contain '/', and can't be '{}'.\n",
+                    repo.name.bold().green(),
+                    "repositories".bold(),
+                    MPR_REPO_NAME
+                ));
+                quit::with_code(exitcode::CONFIG);
+            }
+
+            repo_names.push(&repo.name);
+        }
+    }
+
+    /// Get the path of the user's configuration file.
+    ///
+    /// Mist runs as root, so this comes from the home directory in the user's
+    /// passwd entry instead of environment variables the user controls, such
+    /// as `HOME` and `XDG_CONFIG_HOME`.
+    fn user_config_path() -> Option<PathBuf> {
+        let user = users::get_user_by_uid(*util::sudo::NORMAL_UID)?;
+
+        let mut path = user.home_dir().to_owned();
+        path.push(".config");
+        path.push("mist");
+        path.push("config.toml");
+        Some(path)
+    }
+
+    /// Load the system-wide configuration file, followed by the user's.
+    fn load() -> Self {
+        let mut config = Self::from_file(Path::new(SYSTEM_CONFIG_PATH));
+
+        if let Some(user_config_path) = Self::user_config_path() {
+            // Read the user's configuration file as the normal user, so that it can't
+            // be pointed at files only root can read.
+            let euid = users::get_effective_uid();
+            util::sudo::to_normal();
+            let user_config = Self::from_file(&user_config_path);
+            users::switch::set_effective_uid(euid).unwrap();
+
+            config = config.merge(user_config);
+        }
+
+        config.validate();
+        config
+    }
+
+    /// The repositories listed in the system-wide configuration file, which
+    /// unlike those in users' own configuration files are shared by everyone.
+    pub fn system_repositories() -> Vec<MprRepository> {
+        Self::from_file(Path::new(SYSTEM_CONFIG_PATH))
+            .repositories
+            .unwrap_or_default()
+    }
+
+    /// The MPR URL to use when one isn't passed on the command line.
+    pub fn mpr_url(&self) -> &str {
+        self.mpr_url.as_deref().unwrap_or(DEFAULT_MPR_URL)
+    }
+
+    /// The repositories to get MPR packages from, with the highest priority
+    /// ones first. The MPR itself is always included, at a priority of `0`.
+    pub fn repositories(&self, mpr_url: &str) -> Vec<MprRepository> {
+        let mut repos = vec![MprRepository {
+            name: MPR_REPO_NAME.to_owned(),
+            url: mpr_url.to_owned(),
+            priority: 0,
+        }];
+        repos.extend(self.repositories.iter().flatten().cloned());
+
+        // This is a stable sort, so the MPR stays ahead of repositories with the same
+        // priority.
+        repos.sort_by(|a, b| b.priority.cmp(&a.priority));
+        repos
+    }
+
+    /// The paging mode to use when one isn't passed on the command line.
+    pub fn paging(&self) -> &str {
+        self.paging.as_deref().unwrap_or("auto")
+    }
+
+    /// Get the editor to open files with, falling back to the one from the
+    /// environment.
+    pub fn get_editor(&self) -> io::Result<PathBuf> {
+        match &self.editor {
+            Some(editor) => Ok(PathBuf::from(editor)),
+            None => edit::get_editor(),
+        }
+    }
+
+    /// The extra arguments to pass to `makedeb`.
+    pub fn makedeb_args(&self) -> &[String] {
+        self.makedeb_args.as_deref().unwrap_or_default()
+    }
+
+    /// The MPR maintainers whose packages are trusted.
+    pub fn trusted_maintainers(&self) -> &[String] {
+        self.trusted_maintainers.as_deref().unwrap_or_default()
+    }
+
+    /// The MPR package bases that are trusted.
+    pub fn trusted_pkgbases(&self) -> &[String] {
+        self.trusted_pkgbases.as_deref().unwrap_or_default()
+    }
+}
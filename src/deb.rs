@@ -0,0 +1,110 @@
+use flate2::{write::GzEncoder, Compression};
+use std::{fs, io, path::Path};
+
+/// Create a gzipped tarball containing the given files, along with the `./`
+/// directory that `dpkg-deb` puts at the start of its archives.
+fn gzipped_tarball(files: &[(&str, &[u8])]) -> io::Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+
+    let mut dir_header = tar::Header::new_gnu();
+    dir_header.set_entry_type(tar::EntryType::Directory);
+    dir_header.set_mode(0o755);
+    dir_header.set_size(0);
+    builder.append_data(&mut dir_header, "./", io::empty())?;
+
+    for (path, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_size(data.len() as u64);
+        builder.append_data(&mut header, path, *data)?;
+    }
+
+    builder.into_inner()?.finish()
+}
+
+/// Create a `.deb` file that contains the given control file and no other
+/// files, the same as `dpkg-deb -b` would for a directory only containing
+/// `DEBIAN/control`.
+fn control_only_deb(control: &str) -> io::Result<Vec<u8>> {
+    let control_tarball = gzipped_tarball(&[("./control", control.as_bytes())])?;
+    let data_tarball = gzipped_tarball(&[])?;
+
+    let mut builder = ar::Builder::new(Vec::new());
+
+    for (identifier, data) in [
+        ("debian-binary", b"2.0\n".as_slice()),
+        ("control.tar.gz", control_tarball.as_slice()),
+        ("data.tar.gz", data_tarball.as_slice()),
+    ] {
+        let mut header = ar::Header::new(identifier.as_bytes().to_vec(), data.len() as u64);
+        header.set_mode(0o100644);
+        builder.append(&header, data)?;
+    }
+
+    builder.into_inner()
+}
+
+/// Write a `.deb` file to `path` that contains the given control file and no
+/// other files.
+pub fn write_control_only_deb(path: &Path, control: &str) -> io::Result<()> {
+    fs::write(path, control_only_deb(control)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    /// Get the paths and contents of the files in a gzipped tarball.
+    fn tarball_files(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut archive = tar::Archive::new(GzDecoder::new(data));
+        let mut files = vec![];
+
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().display().to_string();
+            let mut contents = vec![];
+            entry.read_to_end(&mut contents).unwrap();
+            files.push((path, contents));
+        }
+
+        files
+    }
+
+    #[test]
+    fn control_only() {
+        let control = "Package: foo\nVersion: 1.0-1\nArchitecture: all\n";
+        let deb = control_only_deb(control).unwrap();
+
+        let mut archive = ar::Archive::new(deb.as_slice());
+        let mut members = vec![];
+
+        while let Some(entry) = archive.next_entry() {
+            let mut entry = entry.unwrap();
+            let identifier = String::from_utf8(entry.header().identifier().to_vec()).unwrap();
+            let mut contents = vec![];
+            entry.read_to_end(&mut contents).unwrap();
+            members.push((identifier, contents));
+        }
+
+        // dpkg needs the members in this exact order.
+        let identifiers: Vec<&str> = members.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            identifiers,
+            ["debian-binary", "control.tar.gz", "data.tar.gz"]
+        );
+        assert_eq!(members[0].1, b"2.0\n");
+
+        let control_files = tarball_files(&members[1].1);
+        assert_eq!(control_files.len(), 2);
+        assert_eq!(control_files[0].0, "./");
+        assert_eq!(control_files[1].0, "control");
+        assert_eq!(control_files[1].1, control.as_bytes());
+
+        let data_files = tarball_files(&members[2].1);
+        assert_eq!(data_files.len(), 1);
+        assert_eq!(data_files[0].0, "./");
+    }
+}
@@ -0,0 +1,47 @@
+use crate::{cache::MprCache, message, style::Colorize, util};
+use serde_json::json;
+
+pub fn flag(args: &clap::ArgMatches) {
+    let pkgbase: &String = args.get_one("pkg").unwrap();
+    let mpr_url: &String = args.get_one("mpr-url").unwrap();
+    let api_token = util::get_api_token(args);
+
+    MprCache::new().ensure_pkgbase(pkgbase);
+
+    // If no reason was supplied, get one from the user.
+    let reason = match args.get_one::<String>("msg") {
+        Some(msg) => msg.to_owned(),
+        None => util::edit_message("the reason for flagging the package"),
+    };
+
+    if reason.trim().is_empty() {
+        message::error("A reason is needed to flag a package out of date.\n");
+        quit::with_code(exitcode::USAGE);
+    }
+
+    let body = json!({ "reason": reason }).to_string();
+
+    let request = util::AuthenticatedRequest::new(api_token, mpr_url);
+    request.post_checked(&format!("flag/{}", pkgbase), body);
+
+    message::info(&format!(
+        "Flagged '{}' out of date.\n",
+        pkgbase.bold().green()
+    ));
+}
+
+pub fn unflag(args: &clap::ArgMatches) {
+    let pkgbase: &String = args.get_one("pkg").unwrap();
+    let mpr_url: &String = args.get_one("mpr-url").unwrap();
+    let api_token = util::get_api_token(args);
+
+    MprCache::new().ensure_pkgbase(pkgbase);
+
+    let request = util::AuthenticatedRequest::new(api_token, mpr_url);
+    request.post_checked(&format!("unflag/{}", pkgbase), String::new());
+
+    message::info(&format!(
+        "Removed the out of date flag from '{}'.\n",
+        pkgbase.bold().green()
+    ));
+}
@@ -0,0 +1,450 @@
+use crate::{
+    cache::{Cache, CommitOptions, MprCache},
+    config::CONFIG,
+    message,
+    style::Colorize,
+    util,
+};
+use chrono::{TimeZone, Utc};
+use rust_apt::cache::Cache as AptCache;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+};
+
+/// The directory the history log is stored in.
+const HISTORY_DIR: &str = "/var/log/mist";
+
+/// The history log, which contains one JSON-encoded [`Transaction`] per line.
+const HISTORY_PATH: &str = "/var/log/mist/history.jsonl";
+
+/// What happened to a package during a transaction.
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Install,
+    Remove,
+    Purge,
+    Upgrade,
+    Downgrade,
+}
+
+impl Action {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Install => "install",
+            Self::Remove => "remove",
+            Self::Purge => "purge",
+            Self::Upgrade => "upgrade",
+            Self::Downgrade => "downgrade",
+        }
+    }
+}
+
+/// A change made to a package during a transaction.
+#[derive(Deserialize, Serialize)]
+pub struct PackageChange {
+    pkgname: String,
+    action: Action,
+    /// The version that was installed before the transaction.
+    old_version: Option<String>,
+    /// The version that was installed by the transaction.
+    new_version: Option<String>,
+}
+
+/// A package that was built from an MPR package base.
+#[derive(Deserialize, Serialize)]
+pub struct BuiltPackage {
+    pub pkgname: String,
+    pub version: String,
+}
+
+/// An MPR package base that was built during a transaction.
+#[derive(Deserialize, Serialize)]
+pub struct MprBuild {
+    pub pkgbase: String,
+    /// The Git commit the package base was built from.
+    pub commit: String,
+    pub packages: Vec<BuiltPackage>,
+}
+
+/// A transaction recorded in the history log.
+#[derive(Deserialize, Serialize)]
+struct Transaction {
+    id: u32,
+    /// When the transaction was started, as a Unix timestamp.
+    date: i64,
+    /// The command that started the transaction.
+    command: String,
+    changes: Vec<PackageChange>,
+    mpr_builds: Vec<MprBuild>,
+}
+
+impl Transaction {
+    /// Read every transaction in the history log.
+    fn load_all() -> Vec<Self> {
+        let contents = match fs::read_to_string(HISTORY_PATH) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return vec![],
+            Err(err) => {
+                message::error(&format!(
+                    "Failed to read the history log at '{}' [{}]\n",
+                    HISTORY_PATH.bold().green(),
+                    err.to_string().bold()
+                ));
+                quit::with_code(exitcode::IOERR);
+            }
+        };
+
+        let mut transactions = vec![];
+
+        for line in contents.lines() {
+            match serde_json::from_str(line) {
+                Ok(transaction) => transactions.push(transaction),
+                Err(err) => {
+                    message::error(&format!(
+                        "Failed to parse the history log at '{}' [{}]\n",
+                        HISTORY_PATH.bold().green(),
+                        err.to_string().bold()
+                    ));
+                    quit::with_code(exitcode::DATAERR);
+                }
+            }
+        }
+
+        transactions
+    }
+
+    /// Find a transaction in the history log by its ID, aborting if it doesn't
+    /// exist.
+    fn find(id: u32) -> Self {
+        match Self::load_all()
+            .into_iter()
+            .find(|transaction| transaction.id == id)
+        {
+            Some(transaction) => transaction,
+            None => {
+                message::error(&format!(
+                    "Transaction '{}' doesn't exist.\n",
+                    id.to_string().bold().green()
+                ));
+                quit::with_code(exitcode::USAGE);
+            }
+        }
+    }
+
+    /// Add this transaction to the end of the history log, giving it the next
+    /// available ID.
+    ///
+    /// This can run while Mist is aborting, so it can't abort itself and only
+    /// looks at the last transaction to get the ID.
+    fn append(&mut self) -> std::io::Result<()> {
+        let last_id = fs::read_to_string(HISTORY_PATH)
+            .ok()
+            .and_then(|contents| {
+                let last_line = contents.lines().last()?;
+                serde_json::from_str::<Self>(last_line).ok()
+            })
+            .map_or(0, |transaction| transaction.id);
+        self.id = last_id + 1;
+
+        fs::create_dir_all(HISTORY_DIR)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(HISTORY_PATH)?;
+        writeln!(file, "{}", serde_json::to_string(self).unwrap())
+    }
+
+    /// Get a short summary of the changes made by this transaction, i.e. `2
+    /// installed, 1 removed`.
+    fn summary(&self) -> String {
+        let mut counts: Vec<String> = vec![];
+
+        for (action, label) in [
+            (Action::Install, "installed"),
+            (Action::Remove, "removed"),
+            (Action::Purge, "purged"),
+            (Action::Upgrade, "upgraded"),
+            (Action::Downgrade, "downgraded"),
+        ] {
+            let count = self
+                .changes
+                .iter()
+                .filter(|change| change.action == action)
+                .count();
+
+            if count != 0 {
+                counts.push(format!("{} {}", count, label));
+            }
+        }
+
+        counts.join(", ")
+    }
+
+    fn formatted_date(&self) -> String {
+        Utc.timestamp_opt(self.date, 0)
+            .unwrap()
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    }
+}
+
+/// Get the changes marked in an APT cache, so they can be recorded once
+/// they've been made.
+pub fn get_package_changes(cache: &AptCache) -> Vec<PackageChange> {
+    let mut changes = vec![];
+
+    for pkg in cache.get_changes(false) {
+        // Purged packages are marked for deletion too, so they need to be checked for
+        // first.
+        let action = if pkg.marked_install() {
+            Action::Install
+        } else if pkg.marked_purge() {
+            Action::Purge
+        } else if pkg.marked_delete() {
+            Action::Remove
+        } else if pkg.marked_upgrade() {
+            Action::Upgrade
+        } else if pkg.marked_downgrade() {
+            Action::Downgrade
+        } else {
+            continue;
+        };
+
+        let new_version = match action {
+            Action::Remove | Action::Purge => None,
+            _ => pkg.candidate().map(|version| version.version()),
+        };
+
+        changes.push(PackageChange {
+            pkgname: pkg.name(),
+            action,
+            old_version: pkg.installed().map(|version| version.version()),
+            new_version,
+        });
+    }
+
+    changes
+}
+
+/// Records the changes made by [`Cache::commit`] as they happen.
+///
+/// The transaction gets written to the history log when this is dropped, which
+/// also happens when Mist aborts partway through a transaction, so that any
+/// changes that were already made still get recorded.
+pub struct TransactionRecorder {
+    transaction: Transaction,
+}
+
+impl TransactionRecorder {
+    pub fn new() -> Self {
+        Self {
+            transaction: Transaction {
+                id: 0,
+                date: Utc::now().timestamp(),
+                command: env::args().collect::<Vec<String>>().join(" "),
+                changes: vec![],
+                mpr_builds: vec![],
+            },
+        }
+    }
+
+    /// Record package changes that were successfully made.
+    pub fn add_changes(&mut self, mut changes: Vec<PackageChange>) {
+        self.transaction.changes.append(&mut changes);
+    }
+
+    /// Record MPR package bases that were successfully built and installed.
+    pub fn add_mpr_builds(&mut self, mut builds: Vec<MprBuild>) {
+        self.transaction.mpr_builds.append(&mut builds);
+    }
+}
+
+impl Drop for TransactionRecorder {
+    fn drop(&mut self) {
+        if self.transaction.changes.is_empty() {
+            return;
+        }
+
+        if let Err(err) = self.transaction.append() {
+            message::warning(&format!(
+                "Failed to record the transaction in the history log at '{}' [{}]\n",
+                HISTORY_PATH.bold().green(),
+                err.to_string().bold()
+            ));
+        }
+    }
+}
+
+fn list() {
+    let transactions = Transaction::load_all();
+
+    if transactions.is_empty() {
+        println!("{}", "No transactions have been recorded yet.".bold());
+        return;
+    }
+
+    for transaction in transactions.iter().rev() {
+        println!(
+            "{} {} {}",
+            format!("[{}]", transaction.id).bold().green(),
+            transaction.formatted_date().bold(),
+            transaction.command
+        );
+        println!("    {}", transaction.summary());
+    }
+}
+
+fn show(id: u32) {
+    let transaction = Transaction::find(id);
+
+    println!("{} {}", "ID:".bold(), transaction.id);
+    println!("{} {}", "Date:".bold(), transaction.formatted_date());
+    println!("{} {}", "Command:".bold(), transaction.command);
+
+    println!("\n{}", "Changes:".bold());
+
+    for change in &transaction.changes {
+        let versions = match (&change.old_version, &change.new_version) {
+            (Some(old), Some(new)) => format!("{} -> {}", old, new),
+            (Some(version), None) | (None, Some(version)) => version.to_owned(),
+            (None, None) => "N/A".to_owned(),
+        };
+
+        println!(
+            "  {} {} ({})",
+            format!("{}:", change.action.as_str()).bold(),
+            change.pkgname.green(),
+            versions
+        );
+    }
+
+    if !transaction.mpr_builds.is_empty() {
+        println!("\n{}", "MPR Builds:".bold());
+
+        for build in &transaction.mpr_builds {
+            let pkgs: Vec<String> = build
+                .packages
+                .iter()
+                .map(|pkg| format!("{}={}", pkg.pkgname, pkg.version))
+                .collect();
+
+            println!(
+                "  {} ({}): {}",
+                build.pkgbase.green(),
+                build.commit,
+                pkgs.join(", ")
+            );
+        }
+    }
+}
+
+fn undo(id: u32, args: &clap::ArgMatches) {
+    let transaction = Transaction::find(id);
+    let mpr_url: &String = args.get_one("mpr-url").unwrap();
+    let options = CommitOptions {
+        assume_yes: args.is_present("yes") || CONFIG.assume_yes.unwrap_or(false),
+        review: false,
+        keep_build_deps: false,
+        simulate: false,
+        json: false,
+        output_dir: None,
+        offline: false,
+    };
+    let cache = Cache::new(AptCache::new(), MprCache::new());
+
+    for change in &transaction.changes {
+        let pkg = cache.apt_cache().get(&change.pkgname);
+        let installed_version = pkg
+            .as_ref()
+            .and_then(|pkg| pkg.installed())
+            .map(|version| version.version());
+
+        match change.action {
+            // Remove anything the transaction installed.
+            Action::Install => {
+                if let Some(pkg) = pkg
+                    && installed_version.is_some()
+                {
+                    pkg.mark_delete(false);
+                    pkg.protect();
+                }
+            }
+            // Put back the versions that were there before the transaction.
+            Action::Remove | Action::Purge | Action::Upgrade | Action::Downgrade => {
+                let Some(old_version) = &change.old_version else {
+                    continue;
+                };
+
+                if installed_version.as_ref() == Some(old_version) {
+                    continue;
+                }
+
+                match pkg.as_ref().and_then(|pkg| pkg.get_version(old_version)) {
+                    Some(version) => {
+                        let pkg = pkg.as_ref().unwrap();
+                        version.set_candidate();
+                        pkg.mark_install(false, true);
+                        pkg.protect();
+                    }
+                    None => message::warning(&format!(
+                        "Version '{}' of package '{}' is no longer available, so not restoring it.\n",
+                        old_version.bold(),
+                        change.pkgname.bold().green()
+                    )),
+                }
+            }
+        }
+    }
+
+    if let Err(err) = cache.apt_cache().resolve(true) {
+        util::handle_errors(&err);
+        quit::with_code(exitcode::UNAVAILABLE);
+    }
+
+    cache.commit(&Vec::new(), mpr_url, &options, &[]);
+}
+
+pub fn history(args: &clap::ArgMatches) {
+    match args.subcommand() {
+        Some(("show", args)) => show(*args.get_one("id").unwrap()),
+        Some(("undo", args)) => undo(*args.get_one("id").unwrap(), args),
+        Some(("list", _)) | None => list(),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSACTION: &str = r#"{"id":3,"date":1641081600,"command":"mist remove --purge foo","changes":[{"pkgname":"foo","action":"purge","old_version":"1.0-1","new_version":null},{"pkgname":"bar","action":"remove","old_version":"2.0-1","new_version":null},{"pkgname":"baz","action":"purge","old_version":"3.0-1","new_version":null}],"mpr_builds":[]}"#;
+
+    #[test]
+    fn parse_transaction() {
+        let transaction: Transaction = serde_json::from_str(TRANSACTION).unwrap();
+
+        assert_eq!(transaction.id, 3);
+        assert_eq!(transaction.command, "mist remove --purge foo");
+        assert!(transaction.changes[0].action == Action::Purge);
+        assert_eq!(transaction.changes[1].old_version.as_deref(), Some("2.0-1"));
+        assert!(transaction.changes[1].new_version.is_none());
+
+        // Transactions are stored one per line, so they have to round trip through a
+        // single line of JSON.
+        let line = serde_json::to_string(&transaction).unwrap();
+        assert!(!line.contains('\n'));
+        assert_eq!(line, TRANSACTION);
+    }
+
+    #[test]
+    fn summary() {
+        let transaction: Transaction = serde_json::from_str(TRANSACTION).unwrap();
+
+        assert_eq!(transaction.summary(), "1 removed, 2 purged");
+        assert_eq!(transaction.formatted_date(), "2022-01-02 00:00:00");
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{cache::MprCache, message, style::Colorize, util};
+use rust_apt::cache::Cache as AptCache;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io::ErrorKind, path::Path, process::Command};
+
+/// The file that pinned package versions are stored in.
+const PINS_PATH: &str = "/var/lib/mist/pins.json";
+
+/// The versions that packages have been pinned to via `mist pin`.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Pins(BTreeMap<String, String>);
+
+impl Pins {
+    /// Load the pinned versions, returning an empty list if none have been set.
+    pub fn load() -> Self {
+        let contents = match fs::read_to_string(PINS_PATH) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Self::default(),
+            Err(err) => {
+                message::error(&format!(
+                    "Failed to read pinned packages from '{}' [{}]\n",
+                    PINS_PATH.bold().green(),
+                    err.to_string().bold()
+                ));
+                quit::with_code(exitcode::IOERR);
+            }
+        };
+
+        match serde_json::from_str(&contents) {
+            Ok(pins) => pins,
+            Err(err) => {
+                message::error(&format!(
+                    "Failed to parse pinned packages from '{}' [{}]\n",
+                    PINS_PATH.bold().green(),
+                    err.to_string().bold()
+                ));
+                quit::with_code(exitcode::DATAERR);
+            }
+        }
+    }
+
+    fn save(&self) {
+        let path = Path::new(PINS_PATH);
+        util::fs::create_dir(path.parent().unwrap().to_str().unwrap());
+
+        if let Err(err) = fs::write(path, serde_json::to_string_pretty(self).unwrap()) {
+            message::error(&format!(
+                "Failed to save pinned packages to '{}' [{}]\n",
+                PINS_PATH.bold().green(),
+                err.to_string().bold()
+            ));
+            quit::with_code(exitcode::IOERR);
+        }
+    }
+
+    /// Get the version a package is pinned to.
+    pub fn get(&self, pkgname: &str) -> Option<&String> {
+        self.0.get(pkgname)
+    }
+}
+
+/// Abort unless every package in `pkglist` is installed, as holds and pins
+/// only apply to installed packages.
+fn check_installed<T: AsRef<str>>(cache: &AptCache, pkglist: &[T]) {
+    let mut not_installed = false;
+
+    for pkg in pkglist {
+        let pkg = pkg.as_ref();
+
+        if !cache.get(pkg).is_some_and(|pkg| pkg.is_installed()) {
+            message::error(&format!(
+                "Package '{}' isn't installed.\n",
+                pkg.bold().green()
+            ));
+            not_installed = true;
+        }
+    }
+
+    if not_installed {
+        quit::with_code(exitcode::USAGE);
+    }
+}
+
+/// Run `apt-mark` with the given action on a list of packages. This updates
+/// the packages' dpkg selections, which covers both APT and MPR packages.
+fn apt_mark(action: &str, pkglist: &[&String]) {
+    let mut cmd = Command::new("apt-mark");
+    cmd.arg(action);
+    cmd.args(pkglist);
+    let status = cmd.spawn().unwrap().wait().unwrap();
+    util::check_exit_status(&cmd, &status);
+}
+
+pub fn hold(args: &clap::ArgMatches) {
+    let pkglist: Vec<&String> = args.get_many("pkg").unwrap().collect();
+
+    check_installed(&AptCache::new(), &pkglist);
+    apt_mark("hold", &pkglist);
+}
+
+pub fn unhold(args: &clap::ArgMatches) {
+    let pkglist: Vec<&String> = args.get_many("pkg").unwrap().collect();
+    let apt_cache = AptCache::new();
+    let mut pins = Pins::load();
+
+    // A package that was removed after being pinned still needs to have its pin
+    // removable, so only packages without a pin have to be installed.
+    let unpinned: Vec<&String> = pkglist
+        .iter()
+        .filter(|pkg| pins.get(pkg).is_none())
+        .copied()
+        .collect();
+    check_installed(&apt_cache, &unpinned);
+
+    let installed: Vec<&String> = pkglist
+        .iter()
+        .filter(|pkg| apt_cache.get(pkg).is_some_and(|pkg| pkg.is_installed()))
+        .copied()
+        .collect();
+
+    if !installed.is_empty() {
+        apt_mark("unhold", &installed);
+    }
+
+    // Unholding a package also removes any pin on it.
+    for pkg in &pkglist {
+        if pins.0.remove(*pkg).is_some() {
+            message::info(&format!("Removed pin on '{}'.\n", pkg.bold().green()));
+        }
+    }
+
+    pins.save();
+}
+
+pub fn pin(args: &clap::ArgMatches) {
+    let pkglist: Vec<&String> = args.get_many("pkg").unwrap().collect();
+    let mut pkg_versions = vec![];
+
+    for pkg in &pkglist {
+        match pkg.split_once('=') {
+            Some((pkgname, version)) if !pkgname.is_empty() && !version.is_empty() => {
+                pkg_versions.push((pkgname, version))
+            }
+            _ => {
+                message::error(&format!(
+                    "Package '{}' needs to be in the form '{}'.\n",
+                    pkg.bold().green(),
+                    "<pkg>=<version>".bold()
+                ));
+                quit::with_code(exitcode::USAGE);
+            }
+        }
+    }
+
+    let apt_cache = AptCache::new();
+    let mpr_cache = MprCache::new();
+    let pkgnames: Vec<&str> = pkg_versions.iter().map(|(pkgname, _)| *pkgname).collect();
+    check_installed(&apt_cache, &pkgnames);
+
+    // Make sure each version can actually be installed, either from APT or the
+    // MPR.
+    for (pkgname, version) in &pkg_versions {
+        let apt_pkg = apt_cache.get(pkgname).unwrap();
+        let mpr_version = mpr_cache.packages().get(*pkgname).map(|pkg| &pkg.version);
+
+        if apt_pkg.get_version(version).is_none()
+            && mpr_version.is_none_or(|mpr_version| mpr_version != version)
+        {
+            message::error(&format!(
+                "Version '{}' of package '{}' isn't available.\n",
+                version.bold(),
+                pkgname.bold().green()
+            ));
+            quit::with_code(exitcode::USAGE);
+        }
+    }
+
+    let mut pins = Pins::load();
+
+    for (pkgname, version) in pkg_versions {
+        pins.0.insert(pkgname.to_owned(), version.to_owned());
+        message::info(&format!(
+            "Pinned '{}' to version '{}'.\n",
+            pkgname.bold().green(),
+            version.bold()
+        ));
+    }
+
+    pins.save();
+}
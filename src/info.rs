@@ -0,0 +1,227 @@
+use crate::{
+    cache::{Cache, MprCache, MprDependencyGroup, MprPackage},
+    message,
+    style::{self, Colorize},
+    util, vote,
+};
+use rust_apt::cache::Cache as AptCache;
+use std::fmt::Write;
+
+/// Format a list of packages as a comma-separated string.
+fn format_pkgs(pkgs: &[String]) -> String {
+    if pkgs.is_empty() {
+        "N/A".to_owned()
+    } else {
+        pkgs.join(", ")
+    }
+}
+
+/// Get the name of the PKGBUILD variable a dependency group comes from, i.e.
+/// `focal_depends_amd64`.
+fn variable_name(name: &str, dep_group: &MprDependencyGroup) -> String {
+    let mut variable = name.to_owned();
+
+    if let Some(distro) = &dep_group.distro {
+        variable = format!("{}_{}", distro, variable);
+    }
+    if let Some(arch) = &dep_group.arch {
+        variable = format!("{}_{}", variable, arch);
+    }
+
+    variable
+}
+
+/// Generate the MPR-specific part of a package's information.
+fn generate_mpr_info(
+    mpr_pkg: &MprPackage,
+    cache: &Cache,
+    mpr_url: &str,
+    distro: &str,
+    arch: &str,
+) -> String {
+    let mut return_string = String::new();
+
+    // Package base, and the other packages it builds.
+    let split_pkgs = cache.mpr_cache().pkgbase_pkgnames(&mpr_pkg.pkgbase);
+
+    write!(
+        return_string,
+        "\n{} {}",
+        "Package Base:".bold(),
+        mpr_pkg.pkgbase
+    )
+    .unwrap();
+    write!(
+        return_string,
+        "\n{} {}",
+        "Split Packages:".bold(),
+        format_pkgs(split_pkgs)
+    )
+    .unwrap();
+    write!(
+        return_string,
+        "\n{} {}",
+        "Git URL:".bold(),
+        cache.mpr_cache().pkgbase_url(&mpr_pkg.pkgbase, mpr_url)
+    )
+    .unwrap();
+
+    // The dependencies that apply to this system.
+    write!(
+        return_string,
+        "\n\n{}",
+        format!("Dependencies ({}/{}):", distro, arch).bold()
+    )
+    .unwrap();
+
+    for (name, pkgs) in [
+        ("Depends", mpr_pkg.get_system_depends(distro, arch)),
+        ("Make Depends", mpr_pkg.get_system_makedepends(distro, arch)),
+        (
+            "Check Depends",
+            mpr_pkg.get_system_checkdepends(distro, arch),
+        ),
+        ("Conflicts", mpr_pkg.get_system_conflicts(distro, arch)),
+        ("Provides", mpr_pkg.get_system_provides(distro, arch)),
+    ] {
+        write!(
+            return_string,
+            "\n  {} {}",
+            format!("{}:", name).bold(),
+            format_pkgs(&pkgs.unwrap_or_default())
+        )
+        .unwrap();
+    }
+
+    // Every distro/architecture variant, as they're declared in the PKGBUILD.
+    write!(return_string, "\n\n{}", "Dependency Variants:".bold()).unwrap();
+    let mut has_variants = false;
+
+    for (name, dep_groups) in [
+        ("depends", &mpr_pkg.depends),
+        ("makedepends", &mpr_pkg.makedepends),
+        ("checkdepends", &mpr_pkg.checkdepends),
+        ("conflicts", &mpr_pkg.conflicts),
+        ("provides", &mpr_pkg.provides),
+    ] {
+        for dep_group in dep_groups {
+            has_variants = true;
+            write!(
+                return_string,
+                "\n  {} {}",
+                format!("{}:", variable_name(name, dep_group)).bold(),
+                format_pkgs(&dep_group.packages)
+            )
+            .unwrap();
+        }
+    }
+
+    if !has_variants {
+        return_string.push_str("\n  N/A");
+    }
+
+    return_string
+}
+
+/// Generate the full information entry for a package.
+fn generate_info_entry(
+    pkgname: &str,
+    cache: &Cache,
+    mpr_url: &str,
+    distro: &str,
+    arch: &str,
+) -> String {
+    let mut return_string = style::generate_pkginfo_entry(pkgname, cache, false);
+    let apt_pkg = cache.apt_cache().get(pkgname);
+    let mpr_pkg = cache.mpr_cache().packages().get(pkgname);
+
+    // Show the versions available from each source next to each other.
+    let apt_candidate = apt_pkg
+        .as_ref()
+        .and_then(|pkg| pkg.candidate())
+        .map(|version| version.version());
+    let apt_installed = apt_pkg
+        .as_ref()
+        .and_then(|pkg| pkg.installed())
+        .map(|version| version.version());
+
+    write!(
+        return_string,
+        "\n{} {}",
+        "APT Candidate:".bold(),
+        apt_candidate.unwrap_or_else(|| "N/A".to_owned())
+    )
+    .unwrap();
+    write!(
+        return_string,
+        "\n{} {}",
+        "MPR Version:".bold(),
+        mpr_pkg
+            .map(|pkg| pkg.version.clone())
+            .unwrap_or_else(|| "N/A".to_owned())
+    )
+    .unwrap();
+    write!(
+        return_string,
+        "\n{} {}",
+        "Installed:".bold(),
+        apt_installed.unwrap_or_else(|| "N/A".to_owned())
+    )
+    .unwrap();
+
+    if let Some(mpr_pkg) = mpr_pkg {
+        return_string.push_str(&generate_mpr_info(mpr_pkg, cache, mpr_url, distro, arch));
+    }
+
+    return_string
+}
+
+pub fn info(args: &clap::ArgMatches) {
+    let pkglist: Vec<&String> = args.get_many("pkg").unwrap().collect();
+    let mpr_url: &String = args.get_one("mpr-url").unwrap();
+    let mut mpr_cache = MprCache::new();
+
+    // Show whether the user has voted for packages when they're authenticated.
+    // Votes are only extra information here, so failing to fetch them (i.e. when
+    // offline) doesn't stop the rest from being shown.
+    if let Some(api_token) = args.get_one::<String>("token") {
+        vote::try_load_votes(&mut mpr_cache, api_token, mpr_url);
+    }
+
+    let cache = Cache::new(AptCache::new(), mpr_cache);
+    let (system_distro, system_arch) = util::get_distro_arch_info();
+
+    let mut unfindable = false;
+
+    for pkg in &pkglist {
+        if cache.mpr_cache().has_pkgname(pkg) {
+            continue;
+        }
+
+        // Virtual packages don't have any versions to show information for.
+        match cache.apt_cache().get(pkg) {
+            Some(apt_pkg) if apt_pkg.candidate().is_some() => continue,
+            Some(_) => message::error(&format!(
+                "Package '{}' is a virtual package, and so has no information to show.\n",
+                pkg.green().bold()
+            )),
+            None => message::error(&format!(
+                "Unable to find package '{}'.\n",
+                pkg.green().bold()
+            )),
+        }
+
+        unfindable = true;
+    }
+
+    if unfindable {
+        quit::with_code(exitcode::USAGE);
+    }
+
+    let entries: Vec<String> = pkglist
+        .iter()
+        .map(|pkg| generate_info_entry(pkg, &cache, mpr_url, &system_distro, &system_arch))
+        .collect();
+
+    println!("{}", entries.join("\n\n"));
+}
@@ -1,5 +1,6 @@
 use crate::{
-    cache::{Cache, MprCache},
+    cache::{Cache, CommitOptions, MprCache},
+    config::CONFIG,
     install_util, message,
     style::Colorize,
     util,
@@ -7,9 +8,29 @@ use crate::{
 use rust_apt::cache::Cache as AptCache;
 
 pub fn install(args: &clap::ArgMatches) {
-    let pkglist: Vec<&String> = args.get_many("pkg").unwrap().collect();
+    let pkglist: Vec<&str> = args
+        .get_many::<String>("pkg")
+        .unwrap()
+        .map(|pkg| pkg.as_str())
+        .collect();
     let mpr_url: &String = args.get_one("mpr-url").unwrap();
-    let cache = Cache::new(AptCache::new(), MprCache::new());
+    let prefer = args
+        .get_one::<String>("prefer")
+        .or(CONFIG.prefer.as_ref())
+        .map(|prefer| prefer.as_str());
+    let options = CommitOptions::from_args(args);
+
+    // Packages passed as `reponame/pkg` always come from that repository.
+    let mut mpr_cache = MprCache::new();
+    let pkglist = mpr_cache.select_repos(&pkglist);
+    let mpr_selected: Vec<&str> = args
+        .get_many::<String>("pkg")
+        .unwrap()
+        .filter_map(|pkg| pkg.split_once('/'))
+        .map(|(_, pkgname)| pkgname)
+        .collect();
+
+    let cache = Cache::new(AptCache::new(), mpr_cache);
 
     // Package sources.
     let mut apt_pkgs: Vec<&str> = Vec::new();
@@ -36,16 +57,30 @@ pub fn install(args: &clap::ArgMatches) {
     }
 
     for pkg in &pkglist {
+        if mpr_selected.contains(pkg) {
+            mpr_pkgs.push(pkg);
+            continue;
+        }
+
         let apt_pkg = cache.apt_cache().get(pkg);
         let mpr_pkg = cache.mpr_cache().packages().get(*pkg);
 
         if apt_pkg.is_some() && mpr_pkg.is_some() {
-            let resp = util::ask_question(
-                &format!("Package '{}' is available from multiple sources. Please select one to install:\n", pkg.green().bold()),
-                &vec!["APT", "MPR"],
-                false
-            ).remove(0);
-            println!();
+            let resp = match prefer {
+                Some("apt") => "APT".to_string(),
+                Some("mpr") => "MPR".to_string(),
+                // Go with the first option, which is what the question defaults to.
+                _ if options.assume_yes => "APT".to_string(),
+                _ => {
+                    let resp = util::ask_question(
+                        &format!("Package '{}' is available from multiple sources. Please select one to install:\n", pkg.green().bold()),
+                        &vec!["APT", "MPR"],
+                        false
+                    ).remove(0);
+                    println!();
+                    resp
+                }
+            };
 
             if resp == "APT" {
                 apt_pkgs.push(pkg);
@@ -81,5 +116,5 @@ pub fn install(args: &clap::ArgMatches) {
         quit::with_code(exitcode::UNAVAILABLE);
     }
 
-    cache.commit(&mpr_install_order, mpr_url);
+    cache.commit(&mpr_install_order, mpr_url, &options, &[]);
 }
@@ -1,8 +1,19 @@
-use crate::{cache::Cache, message, style::Colorize, util};
+use crate::{cache::Cache, config::CONFIG, message, style::Colorize, util};
 use rust_apt::{cache::Cache as AptCache, tagfile::TagSection};
-use std::{env, fs};
-
-pub fn clone_mpr_pkgs(pkglist: &Vec<&str>, mpr_url: &str) {
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The file in a cached build's directory that lists the `.deb` files in it.
+const BUILD_MANIFEST: &str = "debs.json";
+
+/// Clone the Git repositories of MPR package bases, or update them if they've
+/// already been cloned. When `offline` is set, existing checkouts are used as
+/// they are.
+pub fn clone_mpr_pkgs(cache: &Cache, pkglist: &Vec<&str>, mpr_url: &str, offline: bool) {
     let mut cache_dir = util::xdg::get_cache_dir();
     cache_dir.push("git-pkg");
     util::sudo::to_normal();
@@ -13,9 +24,34 @@ pub fn clone_mpr_pkgs(pkglist: &Vec<&str>, mpr_url: &str) {
     for pkg in pkglist {
         let mut git_dir = cache_dir.clone();
         git_dir.push(pkg);
+        let pkg_url = cache.mpr_cache().pkgbase_url(pkg, mpr_url);
+
+        // A checkout from another repository can't be updated from this one, so
+        // clone it again instead.
+        if !offline
+            && git_dir.is_dir()
+            && git_remote_url(&git_dir).is_some_and(|url| url != pkg_url)
+        {
+            message::info(&format!(
+                "Git repository for '{}' was cloned from a different repository, cloning it again...\n",
+                pkg.green().bold()
+            ));
+
+            util::sudo::to_normal();
+            fs::remove_dir_all(&git_dir).unwrap();
+            util::sudo::to_root();
+        }
 
         // Clone the repository.
         if !git_dir.exists() {
+            if offline {
+                message::error(&format!(
+                    "Git repository for '{}' hasn't been cloned yet, and so can't be used while offline.\n",
+                    pkg.green().bold()
+                ));
+                quit::with_code(exitcode::UNAVAILABLE);
+            }
+
             message::info(&format!(
                 "Cloning '{}' Git repository from the MPR...\n",
                 pkg.green().bold()
@@ -24,7 +60,7 @@ pub fn clone_mpr_pkgs(pkglist: &Vec<&str>, mpr_url: &str) {
             {
                 let mut cmd = util::sudo::run_as_normal_user("git");
                 cmd.arg("clone");
-                cmd.arg(format!("{}/{}", mpr_url, pkg));
+                cmd.arg(&pkg_url);
                 cmd.arg(git_dir.clone().into_os_string().into_string().unwrap());
 
                 let status = cmd.output().unwrap().status;
@@ -48,10 +84,17 @@ pub fn clone_mpr_pkgs(pkglist: &Vec<&str>, mpr_url: &str) {
         } else {
             env::set_current_dir(git_dir).unwrap();
 
-            message::info(&format!(
-                "Making sure Git repository for '{}' is up to date...\n",
-                pkg.green().bold()
-            ));
+            if offline {
+                message::info(&format!(
+                    "Using existing Git repository for '{}' without updating it, as Mist is offline.\n",
+                    pkg.green().bold()
+                ));
+            } else {
+                message::info(&format!(
+                    "Making sure Git repository for '{}' is up to date...\n",
+                    pkg.green().bold()
+                ));
+            }
 
             // Checkout to the right branch.
             {
@@ -61,37 +104,90 @@ pub fn clone_mpr_pkgs(pkglist: &Vec<&str>, mpr_url: &str) {
                 util::check_exit_status(&cmd, &status);
             }
 
-            // Pull from the remote.
-            {
+            // Pull from the remote, falling back to the existing checkout if the
+            // network can't be reached. Any other failure, such as failed
+            // authentication or diverged histories, still errors out.
+            if !offline {
                 let mut cmd = util::sudo::run_as_normal_user("git");
                 cmd.arg("pull");
-                let status = cmd.output().unwrap().status;
-                util::check_exit_status(&cmd, &status);
+                // Make sure Git's messages are in English, so that network errors can be
+                // told apart from the rest.
+                cmd.env("LC_ALL", "C");
+                let output = cmd.output().unwrap();
+                let stderr = String::from_utf8_lossy(&output.stderr);
+
+                if !output.status.success() && is_network_error(&stderr) {
+                    message::warning(&format!(
+                        "Failed to update Git repository for '{}', so using the existing checkout instead. [{}]\n",
+                        pkg.green().bold(),
+                        stderr.trim().bold()
+                    ));
+                } else {
+                    if !output.status.success() {
+                        eprint!("{}", stderr);
+                    }
+
+                    util::check_exit_status(&cmd, &output.status);
+                }
             }
         }
     }
 }
 
+/// Check if a Git command failed because the network couldn't be reached,
+/// going off of its (English) error output.
+fn is_network_error(stderr: &str) -> bool {
+    const NETWORK_ERRORS: &[&str] = &[
+        "Could not resolve host",
+        "Could not resolve proxy",
+        "Temporary failure in name resolution",
+        "Failed to connect to",
+        "Connection refused",
+        "Connection timed out",
+        "Operation timed out",
+        "Network is unreachable",
+        "No route to host",
+    ];
+
+    NETWORK_ERRORS.iter().any(|error| stderr.contains(error))
+}
+
+/// Get the URL a Git checkout was cloned from.
+fn git_remote_url(git_dir: &Path) -> Option<String> {
+    let mut cmd = util::sudo::run_as_normal_user("git");
+    cmd.arg("-C");
+    cmd.arg(git_dir);
+    cmd.args(["remote", "get-url", "origin"]);
+    let output = cmd.output().ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
 /// Order marked MPR packages for installation.
 /// This function assumes all packages in `pkglist` actually exist and that all
 /// changes have already been marked in the `cache` object.
 pub fn order_mpr_packages(cache: &Cache, pkglist: &Vec<&str>) -> Vec<Vec<String>> {
-    let mut cache_dir = util::xdg::get_global_cache_dir();
-    cache_dir.push("deb-pkgs");
-    env::set_current_dir(&cache_dir).unwrap();
-
-    // Get the list of MPR packages on this system. These are created in
-    // [`crate::update::update`].
-    let mut debs_owned = vec![];
-
-    for path in fs::read_dir("./").unwrap() {
-        let filename = path.unwrap().file_name().into_string().unwrap();
-
-        if filename.ends_with(".deb") {
-            debs_owned.push(filename);
-        }
-    }
-
+    // Get the list of MPR packages on this system, from the repository each one
+    // is taken from.
+    let placeholder_dirs = cache.mpr_cache().placeholder_dirs();
+    let control_file = |pkgname: &str| {
+        placeholder_dirs.get(pkgname).and_then(|dir| {
+            fs::read_to_string(dir.join(pkgname).join("DEBIAN").join("control")).ok()
+        })
+    };
+
+    let debs_owned: Vec<String> = placeholder_dirs
+        .iter()
+        .map(|(pkgname, dir)| {
+            dir.join(format!("{}.deb", pkgname))
+                .into_os_string()
+                .into_string()
+                .unwrap()
+        })
+        .collect();
     let debs: Vec<&str> = debs_owned.iter().map(|s| s.as_str()).collect();
 
     // Create a new cache object that we'll use to find what packages are to be
@@ -131,9 +227,7 @@ pub fn order_mpr_packages(cache: &Cache, pkglist: &Vec<&str>) -> Vec<Vec<String>
         let pkg = new_cache.get(pkg_str).unwrap();
 
         // Get the package's version in its control file.
-        let tagsection =
-            TagSection::new(&fs::read_to_string(pkg_str.to_string() + "/DEBIAN/control").unwrap())
-                .unwrap();
+        let tagsection = TagSection::new(&control_file(pkg_str).unwrap()).unwrap();
         let version = tagsection.get("Version").unwrap();
 
         pkg.get_version(version).unwrap().set_candidate();
@@ -155,7 +249,7 @@ pub fn order_mpr_packages(cache: &Cache, pkglist: &Vec<&str>) -> Vec<Vec<String>
     for pkg in new_cache.get_changes(false) {
         let mut invalid_change: Option<&str> = None;
         let mpr_pkg_change = {
-            if let Ok(string) = fs::read_to_string(pkg.name() + "/DEBIAN/control")
+            if let Some(string) = control_file(&pkg.name())
                 && let Ok(tagsection) = TagSection::new(&string)
                 && tagsection.get("Version").unwrap() == &pkg.candidate().unwrap().version() {
                     true
@@ -182,7 +276,10 @@ pub fn order_mpr_packages(cache: &Cache, pkglist: &Vec<&str>) -> Vec<Vec<String>
                 {
                     invalid_change = Some("install");
                 }
-                (!mpr_pkg_change).then(|| assert!(normal_pkg.mark_install(false, true)));
+                // Keep the auto-installed state from the resolver, so that build
+                // dependencies can be cleaned up once MPR packages are built.
+                (!mpr_pkg_change)
+                    .then(|| assert!(normal_pkg.mark_install(false, !pkg.is_auto_installed())));
             } else if pkg.marked_delete() {
                 if !normal_pkg_keep && !normal_pkg.marked_delete() {
                     invalid_change = Some("delete");
@@ -344,3 +441,296 @@ pub fn pkgnames_to_pkgbases(cache: &Cache, pkglist: &Vec<Vec<String>>) -> Vec<Ve
 
     returned_vec
 }
+
+/// A `.deb` file built from an MPR package base.
+#[derive(Deserialize, Serialize)]
+pub struct BuiltDeb {
+    pub pkgname: String,
+    pub version: String,
+    /// The file name of the `.deb`, inside of [`PkgbaseBuild::dir`].
+    pub filename: String,
+}
+
+/// The result of building an MPR package base.
+pub struct PkgbaseBuild {
+    /// The Git commit the package base was built from.
+    pub commit: String,
+    /// The directory the built `.deb` files are in.
+    pub dir: PathBuf,
+    pub debs: Vec<BuiltDeb>,
+}
+
+/// Get the directory that built MPR packages are cached in. Each package base
+/// has its own directory in here, which has a subdirectory for each build
+/// named after the version, architecture and Git commit it was built from, and
+/// the `makedeb` arguments it was built with.
+pub fn get_build_cache_dir() -> PathBuf {
+    let mut build_cache_dir = util::xdg::get_cache_dir();
+    build_cache_dir.push("builds");
+    build_cache_dir
+}
+
+/// Get the version of the package base checked out in `git_dir` from its
+/// `.SRCINFO` file.
+fn srcinfo_version(git_dir: &Path) -> Option<String> {
+    let srcinfo = fs::read_to_string(git_dir.join(".SRCINFO")).ok()?;
+    let field = |name: &str| {
+        srcinfo.lines().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == name).then(|| value.trim().to_owned())
+        })
+    };
+
+    let version = format!("{}-{}", field("pkgver")?, field("pkgrel")?);
+
+    match field("epoch") {
+        Some(epoch) => Some(format!("{}:{}", epoch, version)),
+        None => Some(version),
+    }
+}
+
+/// Get a short hash of the arguments passed to `makedeb`, so that changing them
+/// doesn't reuse builds made with the old ones.
+fn makedeb_args_hash(makedeb_args: &[String]) -> String {
+    let mut hasher = Sha256::new();
+
+    for arg in makedeb_args {
+        hasher.update(arg.as_bytes());
+        hasher.update([0]);
+    }
+
+    format!("{:x}", hasher.finalize())[..16].to_owned()
+}
+
+/// Get the `.deb` files from a previous build, if there is one.
+fn read_cached_build(build_dir: &Path) -> Option<Vec<BuiltDeb>> {
+    let manifest = fs::read_to_string(build_dir.join(BUILD_MANIFEST)).ok()?;
+    let debs: Vec<BuiltDeb> = serde_json::from_str(&manifest).ok()?;
+
+    debs.iter()
+        .all(|deb| build_dir.join(&deb.filename).exists())
+        .then_some(debs)
+}
+
+/// Copy the `.deb` files from a build into `build_dir`, so they can be reused
+/// by later builds of the same commit.
+///
+/// The files get copied into a temporary directory first, so a partially
+/// copied build never gets used.
+fn cache_build(build: &PkgbaseBuild, build_dir: &Path) -> io::Result<()> {
+    let tmp_dir = PathBuf::from(format!("{}.tmp", build_dir.display()));
+
+    // The build cache is in the user's cache directory, so make sure they own it.
+    util::sudo::to_normal();
+
+    let result = (|| {
+        if tmp_dir.exists() {
+            fs::remove_dir_all(&tmp_dir)?;
+        }
+        if build_dir.exists() {
+            fs::remove_dir_all(build_dir)?;
+        }
+
+        fs::create_dir_all(&tmp_dir)?;
+
+        for deb in &build.debs {
+            fs::copy(build.dir.join(&deb.filename), tmp_dir.join(&deb.filename))?;
+        }
+
+        fs::write(
+            tmp_dir.join(BUILD_MANIFEST),
+            serde_json::to_string(&build.debs).unwrap(),
+        )?;
+        fs::rename(&tmp_dir, build_dir)
+    })();
+
+    util::sudo::to_root();
+    result
+}
+
+/// Copy the `.deb` files from a build into `output_dir`, creating it if it
+/// doesn't exist.
+pub fn copy_debs(build: &PkgbaseBuild, output_dir: &Path) {
+    // The output directory is the user's, so make sure they own what's in it.
+    util::sudo::to_normal();
+
+    let result = (|| {
+        fs::create_dir_all(output_dir)?;
+
+        for deb in &build.debs {
+            fs::copy(
+                build.dir.join(&deb.filename),
+                output_dir.join(&deb.filename),
+            )?;
+        }
+
+        Ok::<(), io::Error>(())
+    })();
+
+    util::sudo::to_root();
+
+    if let Err(err) = result {
+        message::error(&format!(
+            "Failed to copy built packages to '{}' [{}]\n",
+            output_dir.display().to_string().bold().green(),
+            err.to_string().bold()
+        ));
+        quit::with_code(exitcode::IOERR);
+    }
+
+    for deb in &build.debs {
+        message::info(&format!(
+            "Copied '{}' to '{}'.\n",
+            deb.filename.bold().green(),
+            output_dir.display().to_string().bold()
+        ));
+    }
+}
+
+/// Build an MPR package base that's been cloned into `git_dir`.
+///
+/// If the same version of the package base was already built from the same
+/// commit for this architecture with the same `makedeb` arguments, the `.deb`
+/// files from that build are used instead of running `makedeb` again.
+pub fn build_pkgbase(pkgbase: &str, git_dir: &Path) -> PkgbaseBuild {
+    env::set_current_dir(git_dir).unwrap();
+
+    let commit = {
+        let mut cmd = util::sudo::run_as_normal_user("git");
+        cmd.args(["rev-parse", "HEAD"]);
+        let output = cmd.output().unwrap();
+        util::check_exit_status(&cmd, &output.status);
+        String::from_utf8(output.stdout).unwrap().trim().to_owned()
+    };
+
+    // Builds can only be cached if we know what version they're for.
+    let build_dir = srcinfo_version(git_dir).map(|version| {
+        let (_, system_arch) = util::get_distro_arch_info();
+        let mut build_dir = get_build_cache_dir();
+        build_dir.push(pkgbase);
+        build_dir.push(format!(
+            "{}_{}_{}_{}",
+            version,
+            system_arch,
+            commit,
+            makedeb_args_hash(CONFIG.makedeb_args())
+        ));
+        build_dir
+    });
+
+    if let Some(build_dir) = &build_dir
+        && let Some(debs) = read_cached_build(build_dir)
+    {
+        message::info(&format!(
+            "Using previously built packages for '{}'...\n",
+            pkgbase.green()
+        ));
+
+        return PkgbaseBuild {
+            commit,
+            dir: build_dir.clone(),
+            debs,
+        };
+    }
+
+    // See this package has a control field value of 'MPR-Package'. If it does,
+    // don't add it to our arg list. TODO: We need to add this key
+    // to makedeb's .SRCINFO files.
+    let mpr_package_field = {
+        let mut cmd = util::sudo::run_as_normal_user("bash");
+        cmd.arg("-c");
+        cmd.arg(
+            "source PKGBUILD; printf '%s\n' \"${control_fields[@]}\" | grep -q '^MPR-Package:'",
+        );
+        cmd.output().unwrap().status.success()
+    };
+
+    let mut cmd = util::sudo::run_as_normal_user("makedeb");
+
+    if !mpr_package_field {
+        cmd.arg("-H");
+        cmd.arg("MPR-Package: yes");
+    }
+
+    cmd.args(CONFIG.makedeb_args());
+
+    message::info(&format!("Running makedeb for '{}'...\n", pkgbase.green()));
+    if !cmd.spawn().unwrap().wait().unwrap().success() {
+        message::error("Failed to run makedeb.\n");
+        quit::with_code(exitcode::UNAVAILABLE);
+    }
+
+    // Get the list of '.deb' files that were built.
+    let mut debs = vec![];
+
+    for dir in fs::read_dir("./pkg").unwrap() {
+        let mut path = dir.unwrap().path();
+        path.push("DEBIAN");
+        path.push("control");
+        let control_file = TagSection::new(&fs::read_to_string(&path).unwrap()).unwrap();
+
+        let pkgname = control_file.get("Package").unwrap();
+        let version = control_file.get("Version").unwrap();
+        let arch = control_file.get("Architecture").unwrap();
+
+        debs.push(BuiltDeb {
+            pkgname: pkgname.to_string(),
+            version: version.to_string(),
+            filename: format!("{}_{}_{}.deb", pkgname, version, arch),
+        });
+    }
+
+    let mut build = PkgbaseBuild {
+        commit,
+        dir: git_dir.to_owned(),
+        debs,
+    };
+
+    if let Some(build_dir) = build_dir {
+        match cache_build(&build, &build_dir) {
+            Ok(()) => build.dir = build_dir,
+            Err(err) => message::warning(&format!(
+                "Failed to save the build of '{}' for later use [{}]\n",
+                pkgbase.bold().green(),
+                err.to_string().bold()
+            )),
+        }
+    }
+
+    build
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn makedeb_args_hash_is_stable() {
+        let hash = makedeb_args_hash(&args(&["--no-check"]));
+
+        assert_eq!(hash.len(), 16);
+        assert!(hash.chars().all(|char| char.is_ascii_hexdigit()));
+        assert_eq!(hash, makedeb_args_hash(&args(&["--no-check"])));
+    }
+
+    #[test]
+    fn makedeb_args_hash_changes_with_args() {
+        let hashes = [
+            makedeb_args_hash(&args(&[])),
+            makedeb_args_hash(&args(&["--no-check"])),
+            makedeb_args_hash(&args(&["--no-check", "--skip-pgp-check"])),
+            makedeb_args_hash(&args(&["--skip-pgp-check", "--no-check"])),
+            // Arguments are kept apart, so these don't hash the same.
+            makedeb_args_hash(&args(&["-a", "b"])),
+            makedeb_args_hash(&args(&["-ab"])),
+        ];
+
+        for (index, hash) in hashes.iter().enumerate() {
+            assert!(!hashes[index + 1..].contains(hash));
+        }
+    }
+}
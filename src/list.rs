@@ -1,6 +1,6 @@
 use crate::{
     cache::{Cache, MprCache},
-    style,
+    style, vote,
 };
 use rust_apt::cache::{Cache as AptCache, PackageSort};
 
@@ -13,18 +13,35 @@ pub fn list(args: &clap::ArgMatches) {
     let mpr_only = args.is_present("mpr-only");
     let installed_only = args.is_present("installed-only");
     let name_only = args.is_present("name-only");
+    let output: &String = args.get_one("output").unwrap();
+    let maintainer: Option<&String> = args.get_one("maintainer");
 
-    let cache = Cache::new(AptCache::new(), MprCache::new());
+    let voted_only = args.is_present("voted");
+
+    let mut mpr_cache = MprCache::new();
+
+    if voted_only {
+        vote::load_votes(&mut mpr_cache, args);
+    }
+
+    let cache = Cache::new(AptCache::new(), mpr_cache);
     let mut candidates = Vec::new();
 
     if !pkglist.is_empty() {
         for pkg in pkglist {
-            if cache.apt_cache().get(pkg).is_some()
-                || cache.mpr_cache().packages().get(pkg).is_some()
-            {
+            if cache.apt_cache().get(pkg).is_some() || cache.mpr_cache().has_pkgname(pkg) {
                 candidates.push(pkg.to_string());
             }
         }
+
+        if let Some(maintainer) = maintainer {
+            let maintainer_pkgs = cache.mpr_cache().maintainer_pkgnames(maintainer);
+            candidates.retain(|pkg| maintainer_pkgs.contains(pkg));
+        }
+    } else if let Some(maintainer) = maintainer {
+        // Only MPR packages have maintainers, so there's no need to go through APT's
+        // packages.
+        candidates.extend_from_slice(cache.mpr_cache().maintainer_pkgnames(maintainer));
     } else {
         for pkg in Cache::get_nonvirtual_packages(cache.apt_cache(), &PackageSort::default()) {
             let pkgname = pkg.name();
@@ -44,15 +61,17 @@ pub fn list(args: &clap::ArgMatches) {
         quit::with_code(exitcode::UNAVAILABLE);
     }
 
+    let matches = style::filter_pkgs(
+        &candidates,
+        &cache,
+        apt_only,
+        mpr_only,
+        installed_only,
+        voted_only,
+    );
+
     print!(
         "{}",
-        style::generate_pkginfo_entries(
-            &candidates,
-            &cache,
-            apt_only,
-            mpr_only,
-            installed_only,
-            name_only
-        )
+        style::format_pkginfo_entries(&matches, &cache, name_only, output)
     );
 }
@@ -1,31 +1,90 @@
-use crate::{cache::MprCache, message};
-use bat::{self, PrettyPrinter};
-use chrono::{TimeZone, Utc};
-use serde::Deserialize;
-use std::fmt::Write;
-
-#[derive(Deserialize)]
-struct Comment {
-    date: i64,
-    msg: String,
-    user: String,
+use crate::{cache::MprCache, message, style::Colorize, util};
+use bat::PrettyPrinter;
+use chrono::{NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, fmt::Write};
+
+#[derive(Deserialize, Serialize)]
+pub struct Comment {
+    pub id: u32,
+    pub date: i64,
+    pub msg: String,
+    pub user: String,
+}
+
+/// Get the comments on a package base from the MPR.
+pub fn get_comments(mpr_url: &str, pkgbase: &str) -> Vec<Comment> {
+    let resp = match reqwest::blocking::get(format!("{}/api/list-comments/{}", mpr_url, pkgbase)) {
+        Ok(resp) => resp,
+        Err(err) => {
+            message::error(&format!("Failed to make request. [{}]\n", err));
+            quit::with_code(exitcode::UNAVAILABLE);
+        }
+    };
+
+    let resp_text = resp.text().unwrap();
+
+    match serde_json::from_str::<Vec<Comment>>(&resp_text) {
+        Ok(json) => json,
+        Err(err) => {
+            message::error(&format!("Failed to unpack response. [{}]\n", err));
+            quit::with_code(exitcode::UNAVAILABLE);
+        }
+    }
+}
+
+/// Parse a `YYYY-MM-DD` date into the Unix timestamp at the start of that day.
+fn parse_date(date: &str) -> i64 {
+    match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(date) => date.and_hms_opt(0, 0, 0).unwrap().timestamp(),
+        Err(err) => {
+            message::error(&format!(
+                "Invalid date '{}', expected it in the form 'YYYY-MM-DD' [{}]\n",
+                date.bold().green(),
+                err.to_string().bold()
+            ));
+            quit::with_code(exitcode::USAGE);
+        }
+    }
+}
+
+/// Only keep the comments posted on or after `since` by `author`, newest
+/// first, keeping at most `limit` of them.
+fn filter_comments(
+    comments: Vec<Comment>,
+    since: Option<i64>,
+    author: Option<&str>,
+    limit: Option<usize>,
+) -> Vec<Comment> {
+    let mut comments: Vec<Comment> = comments
+        .into_iter()
+        .filter(|comment| since.is_none_or(|since| comment.date >= since))
+        .filter(|comment| author.is_none_or(|author| comment.user == author))
+        .collect();
+
+    // Show the newest comments first, so that `--limit` keeps the most recent
+    // ones.
+    comments.sort_by_key(|comment| Reverse(comment.date));
+
+    if let Some(limit) = limit {
+        comments.truncate(limit);
+    }
+
+    comments
 }
 
 pub fn list_comments(args: &clap::ArgMatches) {
     let pkgbase: &String = args.get_one("pkg").unwrap();
     let mpr_url: &String = args.get_one("mpr-url").unwrap();
     let paging = args.get_one::<String>("paging").unwrap().as_str();
+    let since = args.get_one::<String>("since").map(|date| parse_date(date));
+    let author: Option<&String> = args.get_one("author");
+    let limit: Option<&usize> = args.get_one("limit");
+    let json = args.is_present("json");
     let mpr_cache = MprCache::new();
 
-    let mut pkgbases: Vec<&String> = Vec::new();
-
-    // Get a list of packages.
-    for pkg in mpr_cache.packages().values() {
-        pkgbases.push(&pkg.pkgbase);
-    }
-
     // Abort if the package base doesn't exist.
-    if !pkgbases.contains(&pkgbase) {
+    if !mpr_cache.has_pkgbase(pkgbase) {
         message::error(&format!(
             "Package base '{}' doesn't exist on the MPR.",
             pkgbase
@@ -33,37 +92,39 @@ pub fn list_comments(args: &clap::ArgMatches) {
         quit::with_code(exitcode::USAGE);
     }
 
-    // Get package comments.
-    let resp = match reqwest::blocking::get(format!("{}/api/list-comments/{}", mpr_url, pkgbase)) {
-        Ok(resp) => resp,
-        Err(err) => {
-            message::error(&format!("Failed to make request. [{}]", err));
-            quit::with_code(exitcode::UNAVAILABLE);
-        }
-    };
+    // Get package comments, only keeping the ones that pass the given filters.
+    let comments = filter_comments(
+        get_comments(mpr_url, pkgbase),
+        since,
+        author.map(|author| author.as_str()),
+        limit.copied(),
+    );
 
-    let resp_text = resp.text().unwrap();
-    let resp_json = match serde_json::from_str::<Vec<Comment>>(&resp_text) {
-        Ok(json) => json,
-        Err(err) => {
-            message::error(&format!("Failed to unpack response. [{}]", err));
-            quit::with_code(exitcode::UNAVAILABLE);
-        }
-    };
+    if json {
+        println!("{}", serde_json::to_string(&comments).unwrap());
+        return;
+    }
+
+    if comments.is_empty() {
+        println!("{}", "No comments were found.".bold());
+        return;
+    }
 
     // Generate a markdown string to show the user.
-    let comments_len = resp_json.len() - 1; // We'll be using indexes to compare against this, so subtract 1.
+    let comments_len = comments.len() - 1; // We'll be using indexes to compare against this, so subtract 1.
     let mut comments_str = String::new();
 
-    for (index, comment) in resp_json.iter().enumerate() {
+    for (index, comment) in comments.iter().enumerate() {
         let date = Utc
-            .timestamp(comment.date, 0)
+            .timestamp_opt(comment.date, 0)
+            .unwrap()
             .format("%Y-%m-%d")
             .to_string();
 
         write!(
             comments_str,
-            "# Date: {}\n# Author: {}\n\n{}",
+            "# ID: {}\n# Date: {}\n# Author: {}\n\n{}",
+            comment.id,
             date,
             comment.user,
             comment.msg.trim()
@@ -75,17 +136,79 @@ pub fn list_comments(args: &clap::ArgMatches) {
         }
     }
 
-    // Get the paging mode from the user.
-    let paging_mode = match paging {
-        "always" => bat::PagingMode::Always,
-        "never" => bat::PagingMode::Never,
-        &_ => bat::PagingMode::QuitIfOneScreen,
-    };
-
     PrettyPrinter::new()
         .input_from_bytes(comments_str.as_bytes())
         .language("md")
-        .paging_mode(paging_mode)
+        .paging_mode(util::paging_mode(paging))
         .print()
         .unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(id: u32, date: i64, user: &str) -> Comment {
+        Comment {
+            id,
+            date,
+            msg: String::new(),
+            user: user.to_owned(),
+        }
+    }
+
+    fn ids(comments: &[Comment]) -> Vec<u32> {
+        comments.iter().map(|comment| comment.id).collect()
+    }
+
+    fn comments() -> Vec<Comment> {
+        vec![
+            comment(1, 100, "alice"),
+            comment(2, 300, "bob"),
+            comment(3, 200, "alice"),
+        ]
+    }
+
+    #[test]
+    fn parse_dates() {
+        assert_eq!(parse_date("1970-01-01"), 0);
+        assert_eq!(parse_date("2022-01-02"), 1641081600);
+    }
+
+    #[test]
+    fn newest_first() {
+        assert_eq!(
+            ids(&filter_comments(comments(), None, None, None)),
+            [2, 3, 1]
+        );
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(
+            ids(&filter_comments(comments(), Some(200), None, None)),
+            [2, 3]
+        );
+        assert_eq!(
+            ids(&filter_comments(comments(), None, Some("alice"), None)),
+            [3, 1]
+        );
+        assert_eq!(
+            ids(&filter_comments(comments(), Some(150), Some("alice"), None)),
+            [3]
+        );
+        assert!(filter_comments(comments(), None, Some("carol"), None).is_empty());
+    }
+
+    #[test]
+    fn limit_keeps_newest() {
+        assert_eq!(
+            ids(&filter_comments(comments(), None, None, Some(2))),
+            [2, 3]
+        );
+        assert_eq!(
+            ids(&filter_comments(comments(), None, Some("alice"), Some(1))),
+            [3]
+        );
+    }
+}
@@ -1,19 +1,31 @@
 #![feature(let_chains)]
+mod build;
 mod cache;
+mod clean;
 mod clone;
 mod comment;
+mod config;
+mod deb;
+mod flag;
+mod history;
+mod hold;
+mod info;
 mod install;
 mod install_util;
 mod list;
 mod list_comments;
 mod message;
 mod progress;
+mod query;
 mod remove;
+mod repo;
+mod review;
 mod search;
 mod style;
 mod update;
 mod upgrade;
 mod util;
+mod vote;
 mod whoami;
 
 use clap::{self, Arg, Command, PossibleValue};
@@ -22,6 +34,7 @@ use std::{
     env,
     fs::File,
     os::{linux::fs::MetadataExt, unix::fs::PermissionsExt},
+    path::PathBuf,
 };
 use style::Colorize;
 use which::which;
@@ -43,7 +56,7 @@ fn get_cli() -> Command<'static> {
         .env("MPR_URL")
         .hide_env_values(true)
         .takes_value(true)
-        .default_value("https://mpr.makedeb.org");
+        .default_value(config::CONFIG.mpr_url());
 
     let mpr_only_arg = Arg::new("mpr-only")
         .help("Filter results to packages available on the MPR")
@@ -58,15 +71,99 @@ fn get_cli() -> Command<'static> {
         .short('i')
         .long("installed");
     
+    let voted_arg = Arg::new("voted")
+        .help("Filter results to MPR packages you've voted for")
+        .long("voted");
+
     let name_only_arg = Arg::new("name-only")
         .help("Output the package's name without any extra details")
         .long("name-only");
 
+    let output_arg = Arg::new("output")
+        .help("The format to output results in")
+        .long("output")
+        .takes_value(true)
+        .default_value("text")
+        .value_parser([
+            PossibleValue::new("text"),
+            PossibleValue::new("json"),
+            PossibleValue::new("tsv")
+        ])
+        .conflicts_with("name-only");
+
+    let yes_arg = Arg::new("yes")
+        .help("Automatically answer prompts (package reviews are still offered unless --no-review is passed)")
+        .short('y')
+        .long("yes")
+        .visible_alias("assume-yes");
+
+    let no_review_arg = Arg::new("no-review")
+        .help("Don't offer to review MPR packages' files before building them")
+        .long("no-review");
+
+    let keep_build_deps_arg = Arg::new("keep-build-deps")
+        .help("Keep the build dependencies of MPR packages installed after building them")
+        .long("keep-build-deps");
+
+    let offline_arg = Arg::new("offline")
+        .help("Don't update Git checkouts, and only install APT packages that have already been downloaded")
+        .long("offline");
+
+    let simulate_arg = Arg::new("simulate")
+        .help("Show what would be done, without changing anything")
+        .short('s')
+        .long("simulate")
+        .visible_alias("dry-run");
+
+    let simulate_output_arg = Arg::new("output")
+        .help("The format to show the simulated transaction in")
+        .long("output")
+        .takes_value(true)
+        .value_parser([
+            PossibleValue::new("text"),
+            PossibleValue::new("json")
+        ])
+        .requires("simulate");
+
     // The CLI.
     Command::new(clap::crate_name!())
         .version(clap::crate_version!())
         .about(clap::crate_description!())
         .arg_required_else_help(true)
+        .subcommand(
+            Command::new("build")
+                .about("Build MPR packages without installing them")
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("pkg")
+                        .help("The package(s) to build")
+                        .multiple_values(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("output-dir")
+                        .help("The directory to copy the built packages to")
+                        .long("output-dir")
+                        .takes_value(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .default_value(".")
+                )
+                .arg(yes_arg.clone())
+                .arg(no_review_arg.clone())
+                .arg(keep_build_deps_arg.clone())
+                .arg(offline_arg.clone())
+                .arg(simulate_arg.clone())
+                .arg(simulate_output_arg.clone())
+                .arg(mpr_url_arg.clone())
+        )
+        .subcommand(
+            Command::new("clean")
+                .about("Remove Git checkouts, built packages and MPR metadata that Mist has cached")
+                .arg(Arg::new("builds").help("Remove built packages and build files").long("builds"))
+                .arg(Arg::new("sources").help("Remove Git checkouts of MPR packages that aren't installed").long("sources"))
+                .arg(Arg::new("metadata").help("Remove MPR metadata downloaded by 'mist update'").long("metadata"))
+                .arg(Arg::new("all").help("Remove everything, including Git checkouts of installed MPR packages").long("all"))
+        )
         .subcommand(
             Command::new("clone")
                 .about("Clone a package base from the MPR")
@@ -93,9 +190,104 @@ fn get_cli() -> Command<'static> {
                         .short('m')
                         .long("msg")
                 )
+                .arg(
+                    Arg::new("edit")
+                        .help("Edit the comment with the given ID instead of posting a new one")
+                        .long("edit")
+                        .takes_value(true)
+                        .value_name("id")
+                        .value_parser(clap::value_parser!(u32))
+                        .conflicts_with("delete")
+                )
+                .arg(
+                    Arg::new("delete")
+                        .help("Delete the comment with the given ID instead of posting a new one")
+                        .long("delete")
+                        .takes_value(true)
+                        .value_name("id")
+                        .value_parser(clap::value_parser!(u32))
+                        .conflicts_with("msg")
+                )
+                .arg(token_arg.clone())
+                .arg(mpr_url_arg.clone())
+        )
+        .subcommand(
+            Command::new("flag")
+                .arg_required_else_help(true)
+                .about("Flag a package base out of date on the MPR")
+                .arg(
+                    Arg::new("pkg")
+                        .help("The package base to flag")
+                        .required(true)
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("msg")
+                        .help("Why the package base is out of date")
+                        .short('m')
+                        .long("msg")
+                        .takes_value(true)
+                )
                 .arg(token_arg.clone())
                 .arg(mpr_url_arg.clone())
         )
+        .subcommand(
+            Command::new("history")
+                .about("View and undo past transactions")
+                .subcommand(
+                    Command::new("list")
+                        .about("List past transactions")
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Show the changes made by a transaction")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("id")
+                                .help("The ID of the transaction")
+                                .required(true)
+                                .value_parser(clap::value_parser!(u32))
+                        )
+                )
+                .subcommand(
+                    Command::new("undo")
+                        .about("Undo the changes made by a transaction")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("id")
+                                .help("The ID of the transaction")
+                                .required(true)
+                                .value_parser(clap::value_parser!(u32))
+                        )
+                        .arg(yes_arg.clone())
+                        .arg(mpr_url_arg.clone().hide(true))
+                )
+        )
+        .subcommand(
+            Command::new("hold")
+                .about("Hold packages at their current version")
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("pkg")
+                        .help("The package(s) to hold")
+                        .multiple_values(true)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            Command::new("info")
+                .about("Show the full details of APT and MPR packages")
+                .visible_alias("show")
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("pkg")
+                        .help("The package(s) to show details for")
+                        .multiple_values(true)
+                        .required(true)
+                )
+                .arg(token_arg.clone().required(false))
+                .arg(mpr_url_arg.clone())
+        )
         .subcommand(
             Command::new("install")
             .about("Install packages from APT and the MPR")
@@ -105,6 +297,22 @@ fn get_cli() -> Command<'static> {
                 .multiple_values(true)
                 .required(true)
             )
+            .arg(
+                Arg::new("prefer")
+                .help("The source to install packages from when they're available from both APT and the MPR")
+                .long("prefer")
+                .takes_value(true)
+                .value_parser([
+                    PossibleValue::new("apt"),
+                    PossibleValue::new("mpr")
+                ])
+            )
+            .arg(yes_arg.clone())
+            .arg(no_review_arg.clone())
+            .arg(keep_build_deps_arg.clone())
+            .arg(offline_arg.clone())
+            .arg(simulate_arg.clone())
+            .arg(simulate_output_arg.clone())
             .arg(mpr_url_arg.clone())
         )
         .subcommand(
@@ -118,7 +326,17 @@ fn get_cli() -> Command<'static> {
             .arg(mpr_only_arg.clone())
             .arg(apt_only_arg.clone())
             .arg(installed_only_arg.clone())
+            .arg(voted_arg.clone())
             .arg(name_only_arg.clone())
+            .arg(output_arg.clone())
+            .arg(token_arg.clone().required(false))
+            .arg(mpr_url_arg.clone())
+            .arg(
+                Arg::new("maintainer")
+                .help("Only list MPR packages maintained by the given user")
+                .long("maintainer")
+                .takes_value(true)
+            )
         )
         .subcommand(
             Command::new("list-comments")
@@ -129,12 +347,39 @@ fn get_cli() -> Command<'static> {
                         .help("The package to view comments for")
                         .required(true)
                 )
+                .arg(
+                    Arg::new("since")
+                        .help("Only show comments posted on or after the given date (in the form YYYY-MM-DD)")
+                        .long("since")
+                        .takes_value(true)
+                        .value_name("date")
+                )
+                .arg(
+                    Arg::new("author")
+                        .help("Only show comments posted by the given user")
+                        .long("author")
+                        .takes_value(true)
+                        .value_name("user")
+                )
+                .arg(
+                    Arg::new("limit")
+                        .help("Only show this many of the newest comments")
+                        .long("limit")
+                        .takes_value(true)
+                        .value_name("count")
+                        .value_parser(clap::value_parser!(usize))
+                )
+                .arg(
+                    Arg::new("json")
+                        .help("Print the comments as JSON")
+                        .long("json")
+                )
                 .arg(
                     Arg::new("paging")
                         .help("When to send output to a pager")
                         .long("paging")
                         .takes_value(true)
-                        .default_value("auto")
+                        .default_value(config::CONFIG.paging())
                         .value_parser([
                             PossibleValue::new("auto"),
                             PossibleValue::new("always"),
@@ -143,6 +388,17 @@ fn get_cli() -> Command<'static> {
                 )
                 .arg(mpr_url_arg.clone())
         )
+        .subcommand(
+            Command::new("pin")
+                .about("Pin packages to a specific version")
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("pkg")
+                        .help("The package(s) to pin, in the form '<pkg>=<version>'")
+                        .multiple_values(true)
+                        .required(true)
+                )
+        )
         .subcommand(
             Command::new("remove")
                 .about("Remove packages from the system")
@@ -162,8 +418,40 @@ fn get_cli() -> Command<'static> {
                         .help("Automatically remove any unneeded packages")
                         .long("autoremove")
                 )
+                .arg(yes_arg.clone())
+                .arg(simulate_arg.clone())
+                .arg(simulate_output_arg.clone())
                 .arg(mpr_url_arg.clone().hide(true))
         )
+        .subcommand(
+            Command::new("repo")
+                .about("Manage the local APT repository that built MPR packages are published to")
+                .subcommand(
+                    Command::new("add")
+                        .about("Add packages to the local repository")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("deb")
+                                .help("The '.deb' file(s) to add")
+                                .multiple_values(true)
+                                .required(true)
+                                .value_parser(clap::value_parser!(PathBuf))
+                        )
+                )
+                .subcommand(Command::new("list").about("List the packages in the local repository"))
+                .subcommand(Command::new("refresh").about("Regenerate the local repository's index"))
+                .subcommand(
+                    Command::new("remove")
+                        .about("Remove packages from the local repository")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("pkg")
+                                .help("The package(s) to remove")
+                                .multiple_values(true)
+                                .required(true)
+                        )
+                )
+        )
         .subcommand(
             Command::new("search")
                 .about("Search for an APT/MPR package")
@@ -171,13 +459,72 @@ fn get_cli() -> Command<'static> {
                 .arg(
                     Arg::new("query")
                         .required(true)
-                        .help("The query to search for")
+                        .help("The query to search for, i.e. 'rust*' or 'maintainer:alice' (see mist(1) for the full syntax)")
                         .multiple_values(true)
                 )
                 .arg(mpr_only_arg.clone())
                 .arg(apt_only_arg.clone())
                 .arg(installed_only_arg.clone())
+                .arg(voted_arg.clone())
                 .arg(name_only_arg.clone())
+                .arg(output_arg.clone())
+                .arg(token_arg.clone().required(false))
+                .arg(mpr_url_arg.clone())
+                .arg(
+                    Arg::new("sort")
+                        .help("How to sort the results")
+                        .long("sort")
+                        .takes_value(true)
+                        .default_value("relevance")
+                        .value_parser([
+                            PossibleValue::new("name"),
+                            PossibleValue::new("votes"),
+                            PossibleValue::new("popularity"),
+                            PossibleValue::new("relevance")
+                        ])
+                )
+                .arg(
+                    Arg::new("limit")
+                        .help("The maximum number of results to show")
+                        .long("limit")
+                        .takes_value(true)
+                        .value_parser(clap::value_parser!(usize))
+                )
+        )
+        .subcommand(
+            Command::new("unflag")
+                .about("Remove the out of date flag from a package base on the MPR")
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("pkg")
+                        .help("The package base to unflag")
+                        .required(true)
+                )
+                .arg(token_arg.clone())
+                .arg(mpr_url_arg.clone())
+        )
+        .subcommand(
+            Command::new("unhold")
+                .about("Remove holds and pins from packages")
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("pkg")
+                        .help("The package(s) to unhold")
+                        .multiple_values(true)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            Command::new("unvote")
+                .about("Remove your vote for a package base on the MPR")
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("pkg")
+                        .help("The package base to remove your vote for")
+                        .required(true)
+                )
+                .arg(token_arg.clone())
+                .arg(mpr_url_arg.clone())
         )
         .subcommand(
             Command::new("update")
@@ -189,6 +536,24 @@ fn get_cli() -> Command<'static> {
                 .about("Upgrade the packages on the system")
                 .arg(Arg::new("apt-only").help("Only upgrade APT packages").long("apt-only").conflicts_with("mpr-only"))
                 .arg(Arg::new("mpr-only").help("Only upgrade MPR packages").long("mpr-only").conflicts_with("apt-only"))
+                .arg(yes_arg.clone())
+                .arg(no_review_arg.clone())
+                .arg(keep_build_deps_arg.clone())
+                .arg(offline_arg.clone())
+                .arg(simulate_arg.clone())
+                .arg(simulate_output_arg.clone())
+                .arg(mpr_url_arg.clone())
+        )
+        .subcommand(
+            Command::new("vote")
+                .about("Vote for a package base on the MPR")
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("pkg")
+                        .help("The package base to vote for")
+                        .required(true)
+                )
+                .arg(token_arg.clone())
                 .arg(mpr_url_arg.clone())
         )
         .subcommand(
@@ -239,13 +604,39 @@ fn main() {
 
     util::sudo::to_root();
 
+    // Simulated transactions don't change anything, so they don't need to be
+    // permission-checked. They run as the normal user instead, so that they can't
+    // do anything the user couldn't do themselves.
+    let (subcommand, subcommand_args) = cmd_results.subcommand().unwrap();
+    let simulating = vec!["build", "install", "remove", "upgrade"].contains(&subcommand)
+        && subcommand_args.is_present("simulate");
+
+    // Undoing a transaction makes changes just like the commands below.
+    let undoing = subcommand == "history" && subcommand_args.subcommand_name() == Some("undo");
+
+    // Only listing the local repository's packages leaves it unchanged.
+    let changing_repo = subcommand == "repo"
+        && subcommand_args
+            .subcommand_name()
+            .is_some_and(|name| name != "list");
+
+    // MPR metadata is shared by all users, so removing it needs permission too.
+    let cleaning_metadata = subcommand == "clean"
+        && (subcommand_args.is_present("metadata") || subcommand_args.is_present("all"));
+
     // If we're running a command that should be permission-checked, then do so.
-    if vec!["install", "remove", "update", "upgrade"].contains(&cmd_results.subcommand().unwrap().0)
+    if undoing
+        || cleaning_metadata
+        || changing_repo
+        || (!simulating
+            && vec![
+                "build", "hold", "install", "pin", "remove", "unhold", "update", "upgrade",
+            ]
+            .contains(&subcommand))
     {
         // If we're running a command that invokes 'makedeb', ensure that we're not
         // running as root.
-        if vec!["install", "upgrade"].contains(&cmd_results.subcommand().unwrap().0)
-            && *util::sudo::NORMAL_UID == 0
+        if vec!["build", "install", "upgrade"].contains(&subcommand) && *util::sudo::NORMAL_UID == 0
         {
             message::error(&format!(
             "This command cannot be ran as root, as it needs to call '{}', which is required to run under a non-root user.\n",
@@ -255,18 +646,32 @@ fn main() {
         }
 
         util::sudo::check_perms();
+    } else if simulating {
+        util::sudo::to_normal();
     }
 
     match cmd_results.subcommand() {
+        Some(("build", args)) => build::build(args),
+        Some(("clean", args)) => clean::clean(args),
         Some(("clone", args)) => clone::clone(args),
         Some(("comment", args)) => comment::comment(args),
+        Some(("flag", args)) => flag::flag(args),
+        Some(("history", args)) => history::history(args),
+        Some(("hold", args)) => hold::hold(args),
+        Some(("info", args)) => info::info(args),
         Some(("install", args)) => install::install(args),
         Some(("list", args)) => list::list(args),
         Some(("list-comments", args)) => list_comments::list_comments(args),
+        Some(("pin", args)) => hold::pin(args),
         Some(("remove", args)) => remove::remove(args),
+        Some(("repo", args)) => repo::repo(args),
         Some(("search", args)) => search::search(args),
+        Some(("unflag", args)) => flag::unflag(args),
+        Some(("unhold", args)) => hold::unhold(args),
+        Some(("unvote", args)) => vote::unvote(args),
         Some(("update", args)) => update::update(args),
         Some(("upgrade", args)) => upgrade::upgrade(args),
+        Some(("vote", args)) => vote::vote(args),
         Some(("whoami", args)) => whoami::whoami(args),
         _ => unreachable!(),
     };
@@ -85,6 +85,68 @@ impl AcquireProgress for MistAcquireProgress {
     }
 }
 
+/// Download progress struct, for files that are fetched outside of APT.
+pub struct MistDownloadProgress {
+    description: String,
+    current_bytes: u64,
+    total_bytes: Option<u64>,
+}
+
+impl MistDownloadProgress {
+    pub fn new(description: String, current_bytes: u64, total_bytes: Option<u64>) -> Self {
+        let progress = Self {
+            description,
+            current_bytes,
+            total_bytes,
+        };
+        progress.draw();
+        progress
+    }
+
+    /// Record that `bytes` more bytes have been downloaded.
+    pub fn inc(&mut self, bytes: u64) {
+        self.current_bytes += bytes;
+        self.draw();
+    }
+
+    /// Finish the progress line, leaving the final state on the screen.
+    pub fn finish(&self) {
+        println!();
+    }
+
+    fn draw(&self) {
+        // Clear the current line.
+        print!("\x1b[2K\x1b[0G");
+
+        let description = format!("{} {}", "Get:".green().bold(), self.description);
+        let size_str = apt_util::unit_str(self.current_bytes, NumSys::Decimal);
+
+        match self.total_bytes {
+            Some(total_bytes) if total_bytes != 0 => {
+                let percent = self.current_bytes as f32 / total_bytes as f32;
+
+                // The length of "Get: ", "100% ()" and the spaces around the progress bar,
+                // along with the size string.
+                let bar_width = apt_util::terminal_width()
+                    .saturating_sub(self.description.len() + size_str.len() + 14);
+
+                print!("{} ", description);
+                if bar_width >= 3 {
+                    print!(
+                        "{} ",
+                        apt_util::get_apt_progress_string(percent, bar_width.try_into().unwrap())
+                            .bold()
+                    );
+                }
+                print!("{}% ({})", (percent * 100.0).round(), size_str);
+            }
+            _ => print!("{} ({})", description, size_str),
+        }
+
+        io::stdout().flush().unwrap();
+    }
+}
+
 /// Install progress struct.
 pub struct MistInstallProgress {}
 
@@ -0,0 +1,432 @@
+use crate::{
+    cache::{MprDependencyGroup, MprPackage},
+    message,
+    style::Colorize,
+};
+use regex::Regex;
+use rust_apt::package::Package;
+
+/// A comparison operator for numeric fields, such as the `>` in `votes:>10`.
+enum Operator {
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+}
+
+/// A comparison against a numeric field.
+struct Comparison {
+    operator: Operator,
+    value: f64,
+}
+
+impl Comparison {
+    fn parse(string: &str) -> Option<Self> {
+        let (operator, value) = if let Some(value) = string.strip_prefix(">=") {
+            (Operator::GreaterEqual, value)
+        } else if let Some(value) = string.strip_prefix("<=") {
+            (Operator::LessEqual, value)
+        } else if let Some(value) = string.strip_prefix('>') {
+            (Operator::Greater, value)
+        } else if let Some(value) = string.strip_prefix('<') {
+            (Operator::Less, value)
+        } else {
+            (Operator::Equal, string.strip_prefix('=').unwrap_or(string))
+        };
+
+        Some(Self {
+            operator,
+            value: value.parse().ok()?,
+        })
+    }
+
+    fn matches(&self, value: f64) -> bool {
+        match self.operator {
+            Operator::Equal => value == self.value,
+            Operator::Greater => value > self.value,
+            Operator::GreaterEqual => value >= self.value,
+            Operator::Less => value < self.value,
+            Operator::LessEqual => value <= self.value,
+        }
+    }
+}
+
+/// A single term in a search query.
+enum Term {
+    /// Matches the package's name or description.
+    Text(Regex),
+    Name(Regex),
+    Description(Regex),
+    Maintainer(Regex),
+    Pkgbase(Regex),
+    Depends(Regex),
+    MakeDepends(Regex),
+    CheckDepends(Regex),
+    Conflicts(Regex),
+    Provides(Regex),
+    Votes(Comparison),
+    Popularity(Comparison),
+    OutOfDate(bool),
+}
+
+/// A search query, made up of terms that all have to match a package.
+pub struct Query {
+    terms: Vec<Term>,
+    /// The lowercased values of any terms that can match a package's name,
+    /// along with their regexes, used to rank results.
+    name_terms: Vec<(String, Regex)>,
+}
+
+/// Convert a user-supplied pattern into a case-insensitive regex.
+///
+/// Patterns wrapped in slashes (i.e. `/^lib.*-dev$/`) are used as regexes
+/// directly, patterns containing `*` or `?` are treated as globs that have to
+/// match the whole value, and anything else matches as a substring.
+fn pattern_to_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    let regex_string = if pattern.len() > 1
+        && let Some(regex) = pattern.strip_prefix('/')
+        && let Some(regex) = regex.strip_suffix('/')
+    {
+        regex.to_owned()
+    } else if pattern.contains(['*', '?']) {
+        let mut regex = String::from("^");
+
+        for char in pattern.chars() {
+            match char {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                _ => regex.push_str(&regex::escape(&char.to_string())),
+            }
+        }
+
+        regex.push('$');
+        regex
+    } else {
+        regex::escape(pattern)
+    };
+
+    Regex::new(&format!("(?i){}", regex_string))
+}
+
+/// Get the package names out of a list of dependency strings, stripping any
+/// prefixes, version restrictions and alternatives.
+pub fn dependency_names<'a, I: IntoIterator<Item = &'a String>>(deps: I) -> Vec<String> {
+    let mut names = vec![];
+
+    for dep in deps {
+        for alternative in dep.split('|') {
+            let alternative = alternative.strip_prefix("p!").unwrap_or(alternative);
+            let name = alternative
+                .split(['<', '>', '='])
+                .next()
+                .unwrap_or_default()
+                .trim();
+            names.push(name.to_owned());
+        }
+    }
+
+    names
+}
+
+/// Get the package names from every distro/architecture variant of a set of
+/// dependency groups.
+fn dep_group_names(dep_groups: &[MprDependencyGroup]) -> Vec<String> {
+    dependency_names(dep_groups.iter().flat_map(|dep_group| &dep_group.packages))
+}
+
+impl Query {
+    /// Parse a query from the terms passed on the command line, aborting if
+    /// any of them are invalid.
+    pub fn new<T: AsRef<str>>(terms: &[T]) -> Self {
+        let mut parsed_terms = vec![];
+        let mut name_terms = vec![];
+
+        for term in terms {
+            let term = term.as_ref();
+
+            match Self::parse_term(term) {
+                Ok(parsed_term) => {
+                    match &parsed_term {
+                        Term::Text(regex) => name_terms.push((term.to_lowercase(), regex.clone())),
+                        Term::Name(regex) => {
+                            let value = term.split_once(':').unwrap().1;
+                            name_terms.push((value.to_lowercase(), regex.clone()));
+                        }
+                        _ => (),
+                    }
+
+                    parsed_terms.push(parsed_term);
+                }
+                Err(err) => {
+                    message::error(&format!(
+                        "Invalid search term '{}' [{}]\n",
+                        term.bold().green(),
+                        err.bold()
+                    ));
+                    quit::with_code(exitcode::USAGE);
+                }
+            }
+        }
+
+        Self {
+            terms: parsed_terms,
+            name_terms,
+        }
+    }
+
+    /// Get how relevant a package's name is to this query. An exact match ranks
+    /// above a prefix match, which ranks above a match anywhere else in the
+    /// name, which ranks above not matching the name at all (i.e. when only the
+    /// description matched).
+    pub fn relevance(&self, pkgname: &str) -> u32 {
+        let pkgname = pkgname.to_lowercase();
+        let mut relevance = 0;
+
+        for (term, regex) in &self.name_terms {
+            if pkgname == *term {
+                relevance += 3;
+            } else if pkgname.starts_with(term.as_str()) {
+                relevance += 2;
+            } else if regex.is_match(&pkgname) {
+                relevance += 1;
+            }
+        }
+
+        relevance
+    }
+
+    /// Parse a single term. Only a recognized field name before the first `:`
+    /// scopes the term to that field, so anything else containing a `:` (i.e.
+    /// `foo:bar` or `/a:b/`) is matched as plain text.
+    fn parse_term(term: &str) -> Result<Term, String> {
+        let text = || {
+            pattern_to_regex(term)
+                .map(Term::Text)
+                .map_err(|err| err.to_string())
+        };
+
+        let Some((field, value)) = term.split_once(':') else {
+            return text();
+        };
+
+        let regex = || pattern_to_regex(value).map_err(|err| err.to_string());
+        let comparison =
+            || Comparison::parse(value).ok_or_else(|| format!("'{}' isn't a valid number", value));
+
+        match field {
+            "name" => Ok(Term::Name(regex()?)),
+            "description" | "desc" => Ok(Term::Description(regex()?)),
+            "maintainer" => Ok(Term::Maintainer(regex()?)),
+            "pkgbase" => Ok(Term::Pkgbase(regex()?)),
+            "depends" => Ok(Term::Depends(regex()?)),
+            "makedepends" => Ok(Term::MakeDepends(regex()?)),
+            "checkdepends" => Ok(Term::CheckDepends(regex()?)),
+            "conflicts" => Ok(Term::Conflicts(regex()?)),
+            "provides" => Ok(Term::Provides(regex()?)),
+            "votes" => Ok(Term::Votes(comparison()?)),
+            "popularity" => Ok(Term::Popularity(comparison()?)),
+            "ood" => match value {
+                "yes" | "true" => Ok(Term::OutOfDate(true)),
+                "no" | "false" => Ok(Term::OutOfDate(false)),
+                _ => Err("'ood' needs to be one of 'yes' or 'no'".to_owned()),
+            },
+            _ => text(),
+        }
+    }
+
+    /// See if a package matches every term in this query. A term matches if it
+    /// matches either the package's APT or MPR data.
+    pub fn matches(
+        &self,
+        pkgname: &str,
+        apt_pkg: Option<&Package>,
+        mpr_pkg: Option<&MprPackage>,
+    ) -> bool {
+        let apt_version = apt_pkg.and_then(|pkg| pkg.candidate());
+
+        let descriptions = || {
+            let mut descriptions = vec![];
+
+            if let Some(desc) = apt_version
+                .as_ref()
+                .and_then(|version| version.description())
+            {
+                descriptions.push(desc);
+            }
+            if let Some(desc) = mpr_pkg.and_then(|pkg| pkg.pkgdesc.clone()) {
+                descriptions.push(desc);
+            }
+
+            descriptions
+        };
+        let any_matches =
+            |regex: &Regex, values: Vec<String>| values.iter().any(|value| regex.is_match(value));
+
+        self.terms.iter().all(|term| match term {
+            Term::Text(regex) => regex.is_match(pkgname) || any_matches(regex, descriptions()),
+            Term::Name(regex) => regex.is_match(pkgname),
+            Term::Description(regex) => any_matches(regex, descriptions()),
+            Term::Maintainer(regex) => mpr_pkg
+                .and_then(|pkg| pkg.maintainer.as_ref())
+                .is_some_and(|maintainer| regex.is_match(maintainer)),
+            Term::Pkgbase(regex) => mpr_pkg.is_some_and(|pkg| regex.is_match(&pkg.pkgbase)),
+            Term::Depends(regex) => {
+                let mut deps = vec![];
+
+                if let Some(version) = &apt_version
+                    && let Some(dep_groups) = version.dependencies()
+                {
+                    for dep_grp in dep_groups {
+                        for dep in &dep_grp.base_deps {
+                            deps.push(dep.name().to_owned());
+                        }
+                    }
+                }
+                if let Some(pkg) = mpr_pkg {
+                    deps.append(&mut dep_group_names(&pkg.depends));
+                }
+
+                any_matches(regex, deps)
+            }
+            Term::MakeDepends(regex) => {
+                mpr_pkg.is_some_and(|pkg| any_matches(regex, dep_group_names(&pkg.makedepends)))
+            }
+            Term::CheckDepends(regex) => {
+                mpr_pkg.is_some_and(|pkg| any_matches(regex, dep_group_names(&pkg.checkdepends)))
+            }
+            Term::Conflicts(regex) => {
+                mpr_pkg.is_some_and(|pkg| any_matches(regex, dep_group_names(&pkg.conflicts)))
+            }
+            Term::Provides(regex) => {
+                let mut provides = vec![];
+
+                if let Some(version) = &apt_version {
+                    for (pkgname, _) in version.provides_list() {
+                        provides.push(pkgname);
+                    }
+                }
+                if let Some(pkg) = mpr_pkg {
+                    provides.append(&mut dep_group_names(&pkg.provides));
+                }
+
+                any_matches(regex, provides)
+            }
+            Term::Votes(comparison) => {
+                mpr_pkg.is_some_and(|pkg| comparison.matches(pkg.num_votes.into()))
+            }
+            Term::Popularity(comparison) => {
+                mpr_pkg.is_some_and(|pkg| comparison.matches(pkg.popularity.into()))
+            }
+            Term::OutOfDate(ood) => mpr_pkg.is_some_and(|pkg| pkg.ood.is_some()) == *ood,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comparison(string: &str) -> (Operator, f64) {
+        let comparison = Comparison::parse(string).unwrap();
+        (comparison.operator, comparison.value)
+    }
+
+    #[test]
+    fn comparison_parse() {
+        assert!(matches!(comparison(">10"), (Operator::Greater, 10.0)));
+        assert!(matches!(comparison(">=10"), (Operator::GreaterEqual, 10.0)));
+        assert!(matches!(comparison("<1.5"), (Operator::Less, 1.5)));
+        assert!(matches!(comparison("<=0"), (Operator::LessEqual, 0.0)));
+        assert!(matches!(comparison("=3"), (Operator::Equal, 3.0)));
+        assert!(matches!(comparison("3"), (Operator::Equal, 3.0)));
+        assert!(Comparison::parse(">abc").is_none());
+        assert!(Comparison::parse("").is_none());
+    }
+
+    #[test]
+    fn comparison_matches() {
+        assert!(Comparison::parse(">10").unwrap().matches(11.0));
+        assert!(!Comparison::parse(">10").unwrap().matches(10.0));
+        assert!(Comparison::parse(">=10").unwrap().matches(10.0));
+        assert!(Comparison::parse("<=10").unwrap().matches(10.0));
+        assert!(!Comparison::parse("<10").unwrap().matches(10.0));
+        assert!(Comparison::parse("10").unwrap().matches(10.0));
+    }
+
+    #[test]
+    fn parse_term_fields() {
+        assert!(matches!(Query::parse_term("name:rust"), Ok(Term::Name(_))));
+        assert!(matches!(
+            Query::parse_term("desc:rust"),
+            Ok(Term::Description(_))
+        ));
+        assert!(matches!(
+            Query::parse_term("maintainer:alice"),
+            Ok(Term::Maintainer(_))
+        ));
+        assert!(matches!(Query::parse_term("votes:>10"), Ok(Term::Votes(_))));
+        assert!(matches!(
+            Query::parse_term("ood:yes"),
+            Ok(Term::OutOfDate(true))
+        ));
+        assert!(matches!(
+            Query::parse_term("ood:no"),
+            Ok(Term::OutOfDate(false))
+        ));
+    }
+
+    #[test]
+    fn parse_term_invalid_values() {
+        assert!(Query::parse_term("votes:many").is_err());
+        assert!(Query::parse_term("ood:maybe").is_err());
+        assert!(Query::parse_term("name:/(/").is_err());
+    }
+
+    #[test]
+    fn parse_term_text() {
+        let Ok(Term::Text(regex)) = Query::parse_term("rust") else {
+            panic!("'rust' wasn't parsed as text");
+        };
+        assert!(regex.is_match("RUSTup"));
+
+        // Terms with a ':' that isn't after a known field are plain text.
+        let Ok(Term::Text(regex)) = Query::parse_term("foo:bar") else {
+            panic!("'foo:bar' wasn't parsed as text");
+        };
+        assert!(regex.is_match("libfoo:bar"));
+
+        let Ok(Term::Text(regex)) = Query::parse_term("/^a:b$/") else {
+            panic!("'/^a:b$/' wasn't parsed as text");
+        };
+        assert!(regex.is_match("a:b"));
+        assert!(!regex.is_match("xa:b"));
+    }
+
+    #[test]
+    fn parse_term_globs() {
+        let Ok(Term::Name(regex)) = Query::parse_term("name:rust-*") else {
+            panic!("'name:rust-*' wasn't parsed as a name term");
+        };
+        assert!(regex.is_match("rust-analyzer"));
+        assert!(!regex.is_match("cargo-rust-foo"));
+    }
+
+    #[test]
+    fn relevance() {
+        let query = Query::new(&["rust"]);
+
+        assert_eq!(query.relevance("rust"), 3);
+        assert_eq!(query.relevance("Rust-analyzer"), 2);
+        assert_eq!(query.relevance("cargo-rust"), 1);
+        assert_eq!(query.relevance("cargo"), 0);
+
+        // Field-scoped terms only count towards relevance if they match names.
+        let query = Query::new(&["name:rust", "maintainer:rust"]);
+        assert_eq!(query.relevance("rust"), 3);
+
+        // Each name term adds to the package's relevance.
+        let query = Query::new(&["rust", "name:rust-*"]);
+        assert_eq!(query.relevance("rust-analyzer"), 2 + 1);
+    }
+}
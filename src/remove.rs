@@ -1,6 +1,7 @@
 use crate::{
     apt_util,
-    cache::{Cache, MprCache},
+    cache::{Cache, CommitOptions, MprCache},
+    config::CONFIG,
     message, util,
 };
 use rust_apt::cache::{Cache as AptCache, PackageSort};
@@ -16,6 +17,19 @@ pub fn remove(args: &clap::ArgMatches) {
     let purge = args.is_present("purge");
     let autoremove = args.is_present("autoremove");
     let mpr_url: &String = args.get_one("mpr-url").unwrap();
+    // Removals never build MPR packages, so there's nothing to review or
+    // clean up afterwards.
+    let options = CommitOptions {
+        assume_yes: args.is_present("yes") || CONFIG.assume_yes.unwrap_or(false),
+        review: false,
+        keep_build_deps: false,
+        simulate: args.is_present("simulate"),
+        json: args
+            .get_one::<String>("output")
+            .is_some_and(|output| output == "json"),
+        output_dir: None,
+        offline: false,
+    };
     let cache = Cache::new(AptCache::new(), MprCache::new());
 
     // Lock the cache.
@@ -59,5 +73,5 @@ pub fn remove(args: &clap::ArgMatches) {
     apt_util::apt_unlock();
 
     // Commit our changes.
-    cache.commit(&Vec::new(), mpr_url);
+    cache.commit(&Vec::new(), mpr_url, &options, &[]);
 }
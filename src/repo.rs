@@ -0,0 +1,340 @@
+use crate::{
+    config::{CONFIG, SYSTEM_CONFIG_PATH},
+    message,
+    style::Colorize,
+    util,
+};
+use chrono::Utc;
+use flate2::{write::GzEncoder, Compression};
+use rust_apt::tagfile::TagSection;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    process::Command,
+};
+
+/// A package in the local repository.
+struct RepoPackage {
+    path: PathBuf,
+    /// The package's control file, without a trailing newline.
+    control: String,
+    pkgname: String,
+    version: String,
+}
+
+/// A flat APT repository that built MPR packages get published to, so that
+/// other systems can install them without building them.
+pub struct Repo {
+    dir: PathBuf,
+}
+
+impl Repo {
+    /// Get the repository set in the system-wide configuration, if there is
+    /// one.
+    pub fn from_config() -> Option<Self> {
+        CONFIG.repo_dir.as_ref().map(|dir| Self {
+            dir: PathBuf::from(dir),
+        })
+    }
+
+    /// Get the repository set in the system-wide configuration, aborting if
+    /// there isn't one.
+    fn from_config_or_quit() -> Self {
+        match Self::from_config() {
+            Some(repo) => repo,
+            None => {
+                message::error(&format!(
+                    "No local repository has been set up. Set '{}' in '{}' to create one.\n",
+                    "repo-dir".bold().green(),
+                    SYSTEM_CONFIG_PATH.bold().green()
+                ));
+                quit::with_code(exitcode::CONFIG);
+            }
+        }
+    }
+
+    /// Create the repository's directory if it doesn't exist yet.
+    fn create_dir(&self) {
+        if let Err(err) = fs::create_dir_all(&self.dir) {
+            message::error(&format!(
+                "Failed to create the local repository at '{}' [{}]\n",
+                self.dir.display().to_string().bold().green(),
+                err.to_string().bold()
+            ));
+            quit::with_code(exitcode::IOERR);
+        }
+    }
+
+    /// Write a file in the repository, aborting if it can't be written.
+    fn write_file(&self, filename: &str, data: &[u8]) {
+        let path = self.dir.join(filename);
+
+        if let Err(err) = fs::write(&path, data) {
+            message::error(&format!(
+                "Failed to write '{}' [{}]\n",
+                path.display().to_string().bold().green(),
+                err.to_string().bold()
+            ));
+            quit::with_code(exitcode::IOERR);
+        }
+    }
+
+    /// Get the packages in the repository, sorted by their file names.
+    fn packages(&self) -> Vec<RepoPackage> {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "deb"))
+                .collect(),
+            Err(err) if err.kind() == ErrorKind::NotFound => vec![],
+            Err(err) => {
+                message::error(&format!(
+                    "Failed to read the local repository at '{}' [{}]\n",
+                    self.dir.display().to_string().bold().green(),
+                    err.to_string().bold()
+                ));
+                quit::with_code(exitcode::IOERR);
+            }
+        };
+        paths.sort();
+
+        let mut pkgs = vec![];
+
+        for path in paths {
+            let mut cmd = Command::new("dpkg-deb");
+            cmd.arg("--field");
+            cmd.arg(&path);
+            let output = cmd.output().unwrap();
+            util::check_exit_status(&cmd, &output.status);
+
+            let control = String::from_utf8(output.stdout)
+                .unwrap()
+                .trim_end()
+                .to_owned();
+            let control_file = TagSection::new(&control).unwrap();
+
+            pkgs.push(RepoPackage {
+                pkgname: control_file.get("Package").unwrap().to_owned(),
+                version: control_file.get("Version").unwrap().to_owned(),
+                control,
+                path,
+            });
+        }
+
+        pkgs
+    }
+
+    /// Copy `.deb` files into the repository, and update its index to include
+    /// them.
+    pub fn add(&self, debs: &[PathBuf]) {
+        self.create_dir();
+
+        for deb in debs {
+            let filename = deb.file_name().unwrap().to_str().unwrap();
+
+            // The packages are given by the user, so read them as the user to make sure
+            // they can only publish files they could read themselves.
+            util::sudo::to_normal();
+            let data = fs::read(deb);
+            util::sudo::to_root();
+
+            match data {
+                Ok(data) => self.write_file(filename, &data),
+                Err(err) => {
+                    message::error(&format!(
+                        "Failed to add '{}' to the local repository [{}]\n",
+                        deb.display().to_string().bold().green(),
+                        err.to_string().bold()
+                    ));
+                    quit::with_code(exitcode::IOERR);
+                }
+            }
+        }
+
+        message::info(&format!(
+            "Published {} packages to the local repository at '{}'.\n",
+            debs.len().to_string().bold(),
+            self.dir.display().to_string().bold().green()
+        ));
+
+        self.update_index();
+    }
+
+    /// Generate the repository's `Packages`, `Packages.gz` and `Release` files,
+    /// and sign the `Release` file if a signing key has been set.
+    fn update_index(&self) {
+        let mut packages = String::new();
+
+        for pkg in self.packages() {
+            let data = fs::read(&pkg.path).unwrap();
+            let filename = pkg.path.file_name().unwrap().to_str().unwrap();
+
+            writeln!(
+                packages,
+                "{}\nFilename: ./{}\nSize: {}\nSHA256: {:x}\n",
+                pkg.control,
+                filename,
+                data.len(),
+                Sha256::digest(&data)
+            )
+            .unwrap();
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(packages.as_bytes()).unwrap();
+        let packages_gz = encoder.finish().unwrap();
+
+        let mut release = format!(
+            "Origin: Mist\nLabel: Mist\nDate: {}\nSHA256:\n",
+            Utc::now().format("%a, %d %b %Y %H:%M:%S UTC")
+        );
+
+        for (filename, data) in [
+            ("Packages", packages.as_bytes()),
+            ("Packages.gz", packages_gz.as_slice()),
+        ] {
+            writeln!(
+                release,
+                " {:x} {} {}",
+                Sha256::digest(data),
+                data.len(),
+                filename
+            )
+            .unwrap();
+            self.write_file(filename, data);
+        }
+
+        self.write_file("Release", release.as_bytes());
+        self.sign_release();
+    }
+
+    /// Sign the `Release` file with the configured GPG key, or remove any old
+    /// signatures if there isn't one.
+    fn sign_release(&self) {
+        let release_path = self.dir.join("Release");
+
+        let Some(key) = &CONFIG.repo_signing_key else {
+            for filename in ["InRelease", "Release.gpg"] {
+                let path = self.dir.join(filename);
+
+                if let Err(err) = fs::remove_file(&path)
+                    && err.kind() != ErrorKind::NotFound
+                {
+                    message::warning(&format!(
+                        "Failed to remove old signature '{}' [{}]\n",
+                        path.display().to_string().bold().green(),
+                        err.to_string().bold()
+                    ));
+                }
+            }
+
+            return;
+        };
+
+        for (sign_arg, filename) in [
+            ("--clearsign", "InRelease"),
+            ("--detach-sign", "Release.gpg"),
+        ] {
+            // The signing key is in the user's keyring, so sign as them.
+            let mut cmd = util::sudo::run_as_normal_user("gpg");
+            cmd.args(["--batch", "--yes", "--armor", "--local-user", key]);
+            cmd.args(["--output", "-", sign_arg]);
+            cmd.arg(&release_path);
+            let output = cmd.output().unwrap();
+            util::check_exit_status(&cmd, &output.status);
+
+            self.write_file(filename, &output.stdout);
+        }
+    }
+}
+
+fn add(args: &clap::ArgMatches) {
+    let repo = Repo::from_config_or_quit();
+    let debs: Vec<PathBuf> = args.get_many::<PathBuf>("deb").unwrap().cloned().collect();
+
+    for deb in &debs {
+        if !deb.is_file() || deb.extension().is_none_or(|ext| ext != "deb") {
+            message::error(&format!(
+                "'{}' isn't a '.deb' file.\n",
+                deb.display().to_string().bold().green()
+            ));
+            quit::with_code(exitcode::USAGE);
+        }
+    }
+
+    repo.add(&debs);
+}
+
+fn remove(args: &clap::ArgMatches) {
+    let repo = Repo::from_config_or_quit();
+    let pkgnames: Vec<&String> = args.get_many("pkg").unwrap().collect();
+    let pkgs = repo.packages();
+
+    for pkgname in &pkgnames {
+        if !pkgs.iter().any(|pkg| pkg.pkgname == **pkgname) {
+            message::error(&format!(
+                "Package '{}' isn't in the local repository.\n",
+                pkgname.bold().green()
+            ));
+            quit::with_code(exitcode::USAGE);
+        }
+    }
+
+    for pkg in pkgs.iter().filter(|pkg| pkgnames.contains(&&pkg.pkgname)) {
+        if let Err(err) = fs::remove_file(&pkg.path) {
+            message::error(&format!(
+                "Failed to remove '{}' [{}]\n",
+                pkg.path.display().to_string().bold().green(),
+                err.to_string().bold()
+            ));
+            quit::with_code(exitcode::IOERR);
+        }
+
+        message::info(&format!(
+            "Removed '{}' version '{}' from the local repository.\n",
+            pkg.pkgname.bold().green(),
+            pkg.version.bold()
+        ));
+    }
+
+    repo.update_index();
+}
+
+fn list() {
+    let repo = Repo::from_config_or_quit();
+    let pkgs = repo.packages();
+
+    if pkgs.is_empty() {
+        println!(
+            "{}",
+            "The local repository doesn't have any packages.".bold()
+        );
+        return;
+    }
+
+    for pkg in pkgs {
+        println!("{} {}", pkg.pkgname.bold().green(), pkg.version);
+    }
+}
+
+fn refresh() {
+    let repo = Repo::from_config_or_quit();
+
+    repo.create_dir();
+    repo.update_index();
+    message::info("Updated the local repository's index.\n");
+}
+
+pub fn repo(args: &clap::ArgMatches) {
+    match args.subcommand() {
+        Some(("add", args)) => add(args),
+        Some(("remove", args)) => remove(args),
+        Some(("refresh", _)) => refresh(),
+        Some(("list", _)) | None => list(),
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{
+    cache::MprCache,
+    config::{CONFIG, MPR_REPO_NAME},
+    message,
+    style::Colorize,
+    util,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The directory that each user's reviewed commits are stored in. Each user
+/// has their own Git checkouts, so a commit one user reviewed doesn't count as
+/// reviewed for anyone else.
+const REVIEWED_DIR: &str = "/var/lib/mist/reviewed";
+
+/// The last commit of each MPR package base that the user reviewed.
+#[derive(Default, Deserialize, Serialize)]
+struct ReviewedCommits(BTreeMap<String, String>);
+
+impl ReviewedCommits {
+    /// Get the path of the file the user's reviewed commits are stored in.
+    fn path() -> PathBuf {
+        Path::new(REVIEWED_DIR).join(format!("{}.json", *util::sudo::NORMAL_UID))
+    }
+
+    /// Load the reviewed commits, returning an empty list if nothing has been
+    /// reviewed yet.
+    fn load() -> Self {
+        let path = Self::path();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Self::default(),
+            Err(err) => {
+                message::error(&format!(
+                    "Failed to read reviewed commits from '{}' [{}]\n",
+                    path.display().to_string().bold().green(),
+                    err.to_string().bold()
+                ));
+                quit::with_code(exitcode::IOERR);
+            }
+        };
+
+        match serde_json::from_str(&contents) {
+            Ok(reviewed) => reviewed,
+            Err(err) => {
+                message::error(&format!(
+                    "Failed to parse reviewed commits from '{}' [{}]\n",
+                    path.display().to_string().bold().green(),
+                    err.to_string().bold()
+                ));
+                quit::with_code(exitcode::DATAERR);
+            }
+        }
+    }
+
+    /// Save the reviewed commits. Failing to do so only means the next review
+    /// shows more than it needs to, so this doesn't abort the transaction.
+    fn save(&self) {
+        let path = Self::path();
+        let result = fs::create_dir_all(REVIEWED_DIR)
+            .and_then(|_| fs::write(&path, serde_json::to_string_pretty(self).unwrap()));
+
+        if let Err(err) = result {
+            message::warning(&format!(
+                "Failed to save reviewed commits to '{}' [{}]\n",
+                path.display().to_string().bold().green(),
+                err.to_string().bold()
+            ));
+        }
+    }
+}
+
+/// Get a Git command that runs in `git_dir` as the normal user.
+fn git_cmd(git_dir: &Path, args: &[&str]) -> Command {
+    let mut cmd = util::sudo::run_as_normal_user("git");
+    cmd.arg("-C");
+    cmd.arg(git_dir);
+    cmd.args(args);
+    cmd
+}
+
+/// Run a Git command in `git_dir`, returning its output.
+fn git_output(git_dir: &Path, args: &[&str]) -> String {
+    let mut cmd = git_cmd(git_dir, args);
+    let output = cmd.output().unwrap();
+    util::check_exit_status(&cmd, &output.status);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Ask the user a question about a package base, returning their response.
+///
+/// If there's no more input to read (i.e. stdin was closed), this returns
+/// [`None`], which callers should take as a "no".
+fn ask(question: &str) -> Option<String> {
+    message::question(question);
+    io::stdout().flush().unwrap();
+
+    let mut resp = String::new();
+
+    if io::stdin().read_line(&mut resp).unwrap() == 0 {
+        println!();
+        return None;
+    }
+
+    resp.pop();
+    Some(resp)
+}
+
+/// Open every file in a package base (besides its `.SRCINFO`) in `editor`.
+fn review_files(git_dir: &Path, editor: &str) {
+    let files: Vec<String> = git_output(git_dir, &["ls-tree", "HEAD", "--name-only"])
+        .lines()
+        // There's no point in having the user review the '.SRCINFO' file.
+        .filter(|file| *file != ".SRCINFO")
+        .map(|file| file.to_owned())
+        .collect();
+
+    let mut cmd = util::sudo::run_as_normal_user(editor);
+    cmd.current_dir(git_dir);
+    cmd.args(files);
+
+    let status = cmd.spawn().unwrap().wait().unwrap();
+    util::check_exit_status(&cmd, &status)
+}
+
+/// Show the changes made to a package base since `commit` in a pager.
+fn review_diff(git_dir: &Path, commit: &str) {
+    let diff_args = ["diff", commit, "HEAD", "--", ".", ":!.SRCINFO"];
+
+    if git_output(git_dir, &diff_args).is_empty() {
+        message::info("Only the '.SRCINFO' file has changed.\n");
+        return;
+    }
+
+    // Pagers can run arbitrary commands, so let Git run it as the normal user
+    // instead of showing the diff from here.
+    let mut args = match CONFIG.paging() {
+        "always" => vec!["--paginate"],
+        "never" => vec!["--no-pager"],
+        _ => vec![],
+    };
+    args.extend(diff_args);
+
+    let mut cmd = git_cmd(git_dir, &args);
+    let status = cmd.spawn().unwrap().wait().unwrap();
+    util::check_exit_status(&cmd, &status);
+}
+
+/// See if a package base is trusted in the user's configuration, either by its
+/// name or by its maintainer, in which case it doesn't need to be reviewed.
+///
+/// Names are only trusted on the repository they're listed for: a bare name
+/// applies to the MPR itself, and names on other repositories need to be
+/// listed as `reponame/name`, so that a user with the same name on another
+/// repository isn't trusted too.
+pub fn is_trusted(mpr_cache: &MprCache, pkgbase: &str) -> bool {
+    let Some(pkg) = mpr_cache
+        .pkgbase_pkgnames(pkgbase)
+        .first()
+        .and_then(|pkgname| mpr_cache.packages().get(pkgname))
+    else {
+        return false;
+    };
+
+    let is_listed = |trusted_names: &[String], name: &str| {
+        trusted_names
+            .iter()
+            .any(|trusted| match trusted.split_once('/') {
+                Some((repo, trusted)) => repo == pkg.repo && trusted == name,
+                None => pkg.repo == MPR_REPO_NAME && trusted == name,
+            })
+    };
+
+    is_listed(CONFIG.trusted_pkgbases(), pkgbase)
+        || pkg
+            .maintainer
+            .as_ref()
+            .is_some_and(|maintainer| is_listed(CONFIG.trusted_maintainers(), maintainer))
+}
+
+/// Offer to review the files of each of the given MPR package bases, which
+/// need to have been cloned already.
+///
+/// If a package base was reviewed before, only the changes since the last
+/// reviewed commit are shown, with the option to review the full files
+/// instead. Package bases that haven't changed since they were last reviewed
+/// are skipped.
+pub fn review_pkgbases(pkgbases: &[&str]) {
+    // Get the editor to review package files with.
+    let editor = match CONFIG.get_editor() {
+        Ok(editor) => editor.into_os_string().into_string().unwrap(),
+        Err(err) => {
+            message::error(&format!(
+                "Couldn't find an editor to review package files with. [{}]\n",
+                err
+            ));
+
+            quit::with_code(exitcode::UNAVAILABLE);
+        }
+    };
+
+    let mut reviewed = ReviewedCommits::load();
+    let mut cache_dir = util::xdg::get_cache_dir();
+    cache_dir.push("git-pkg");
+
+    for pkgbase in pkgbases {
+        let mut git_dir = cache_dir.clone();
+        git_dir.push(pkgbase);
+
+        let head = git_output(&git_dir, &["rev-parse", "HEAD"])
+            .trim()
+            .to_owned();
+
+        // The last reviewed commit can only be diffed against if it's still in the
+        // repository's history.
+        let last_reviewed = reviewed.0.get(*pkgbase).filter(|commit| {
+            git_cmd(
+                &git_dir,
+                &["cat-file", "-e", &format!("{}^{{commit}}", commit)],
+            )
+            .output()
+            .unwrap()
+            .status
+            .success()
+        });
+
+        if last_reviewed == Some(&head) {
+            message::info(&format!(
+                "Skipping review of '{}', as it hasn't changed since it was last reviewed.\n",
+                pkgbase.bold().green()
+            ));
+            continue;
+        }
+
+        println!();
+        let mut was_reviewed = false;
+
+        loop {
+            match last_reviewed {
+                Some(commit) => {
+                    let Some(resp) = ask(&format!(
+                        "Review changes to '{}' since it was last reviewed? [Y/n/(f)ull files] ",
+                        pkgbase.bold().green()
+                    )) else {
+                        break;
+                    };
+
+                    if resp.to_lowercase() == "f" {
+                        review_files(&git_dir, &editor);
+                    } else if util::is_yes(&resp, true) {
+                        review_diff(&git_dir, commit);
+                    } else {
+                        break;
+                    }
+                }
+                None => {
+                    let resp = ask(&format!(
+                        "Review files for '{}'? [Y/n] ",
+                        pkgbase.bold().green()
+                    ));
+
+                    if !resp.is_some_and(|resp| util::is_yes(&resp, true)) {
+                        break;
+                    }
+
+                    review_files(&git_dir, &editor);
+                }
+            }
+
+            was_reviewed = true;
+        }
+
+        if was_reviewed {
+            reviewed.0.insert(pkgbase.to_string(), head);
+        }
+    }
+
+    reviewed.save();
+}
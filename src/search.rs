@@ -1,8 +1,37 @@
 use crate::{
-    cache::{Cache, MprCache},
-    style,
+    cache::{Cache, MprCache, MprPackage},
+    query::Query,
+    style, vote,
 };
 use rust_apt::cache::{Cache as AptCache, PackageSort};
+use std::{cmp::Ordering, collections::HashSet};
+
+/// Sort search results by the given method, falling back to the package name
+/// so that the order stays the same across runs.
+fn sort_pkgs(pkgnames: &mut [&str], sort: &str, query: &Query, cache: &Cache) {
+    let mpr_field = |pkgname: &str, f: fn(&MprPackage) -> f32| {
+        cache
+            .mpr_cache()
+            .packages()
+            .get(pkgname)
+            .map(f)
+            .unwrap_or_default()
+    };
+
+    pkgnames.sort_by(|a, b| {
+        let ordering = match sort {
+            "votes" => mpr_field(*b, |pkg| pkg.num_votes as f32)
+                .total_cmp(&mpr_field(*a, |pkg| pkg.num_votes as f32)),
+            "popularity" => {
+                mpr_field(*b, |pkg| pkg.popularity).total_cmp(&mpr_field(*a, |pkg| pkg.popularity))
+            }
+            "relevance" => query.relevance(b).cmp(&query.relevance(a)),
+            _ => Ordering::Equal,
+        };
+
+        ordering.then_with(|| a.cmp(b))
+    });
+}
 
 pub fn search(args: &clap::ArgMatches) {
     let query_list: Vec<&String> = args.get_many("query").unwrap().collect();
@@ -10,33 +39,38 @@ pub fn search(args: &clap::ArgMatches) {
     let mpr_only = args.is_present("mpr-only");
     let installed_only = args.is_present("installed-only");
     let name_only = args.is_present("name-only");
+    let output: &String = args.get_one("output").unwrap();
+    let sort: &String = args.get_one("sort").unwrap();
+    let limit: Option<&usize> = args.get_one("limit");
+
+    let query = Query::new(&query_list);
+    let voted_only = args.is_present("voted");
+
+    let mut mpr_cache = MprCache::new();
+
+    if voted_only {
+        vote::load_votes(&mut mpr_cache, args);
+    }
 
-    let cache = Cache::new(AptCache::new(), MprCache::new());
+    let cache = Cache::new(AptCache::new(), mpr_cache);
     let mut candidates = Vec::new();
+    let mut apt_pkgnames = HashSet::new();
 
-    for query in query_list {
-        for pkg in Cache::get_nonvirtual_packages(cache.apt_cache(), &PackageSort::default()) {
-            let pkgname = pkg.name();
-            if (pkgname.contains(query)
-                || pkg
-                    .candidate()
-                    .unwrap()
-                    .description()
-                    .unwrap_or_default()
-                    .contains(query))
-                && !candidates.contains(&pkgname)
-            {
-                candidates.push(pkgname);
-            }
+    for pkg in Cache::get_nonvirtual_packages(cache.apt_cache(), &PackageSort::default()) {
+        let pkgname = pkg.name();
+        let mpr_pkg = cache.mpr_cache().packages().get(&pkgname);
+
+        if query.matches(&pkgname, Some(&pkg), mpr_pkg) {
+            candidates.push(pkgname.clone());
         }
 
-        for pkg in cache.mpr_cache().packages().values() {
-            if (pkg.pkgname.contains(query)
-                || pkg.pkgdesc.clone().unwrap_or_default().contains(query))
-                && !candidates.contains(&pkg.pkgname)
-            {
-                candidates.push(pkg.pkgname.to_string());
-            }
+        apt_pkgnames.insert(pkgname);
+    }
+
+    for pkg in cache.mpr_cache().packages().values() {
+        // Packages that are also available via APT were already checked above.
+        if !apt_pkgnames.contains(&pkg.pkgname) && query.matches(&pkg.pkgname, None, Some(pkg)) {
+            candidates.push(pkg.pkgname.to_string());
         }
     }
 
@@ -44,15 +78,22 @@ pub fn search(args: &clap::ArgMatches) {
         quit::with_code(exitcode::UNAVAILABLE);
     }
 
+    let mut matches = style::filter_pkgs(
+        &candidates,
+        &cache,
+        apt_only,
+        mpr_only,
+        installed_only,
+        voted_only,
+    );
+    sort_pkgs(&mut matches, sort, &query, &cache);
+
+    if let Some(limit) = limit {
+        matches.truncate(*limit);
+    }
+
     print!(
         "{}",
-        style::generate_pkginfo_entries(
-            &candidates,
-            &cache,
-            apt_only,
-            mpr_only,
-            installed_only,
-            name_only
-        )
+        style::format_pkginfo_entries(&matches, &cache, name_only, output)
     );
 }
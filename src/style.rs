@@ -4,7 +4,11 @@ use lazy_static::lazy_static;
 
 use chrono::{TimeZone, Utc};
 
-use crate::cache::Cache;
+use crate::{
+    cache::{Cache, MprPackage},
+    config::MPR_REPO_NAME,
+};
+use serde::Serialize;
 use std::fmt::Write;
 
 lazy_static! {
@@ -37,8 +41,15 @@ pub fn generate_pkginfo_entry(pkgname: &str, cache: &Cache, name_only: bool) ->
         if apt_pkg.is_some() {
             sources.push("APT".custom_color(*UBUNTU_PURPLE));
         }
-        if mpr_pkg.is_some() {
-            sources.push("MPR".custom_color(*UBUNTU_PURPLE));
+        // Packages from other MPR-compatible repositories are shown by the name of
+        // their repository.
+        if let Some(mpr_pkg) = mpr_pkg {
+            let source = if mpr_pkg.repo == MPR_REPO_NAME {
+                "MPR"
+            } else {
+                mpr_pkg.repo.as_str()
+            };
+            sources.push(source.custom_color(*UBUNTU_PURPLE));
         }
 
         let mut sources_str = String::new();
@@ -102,6 +113,17 @@ pub fn generate_pkginfo_entry(pkgname: &str, cache: &Cache, name_only: bool) ->
         )
         .unwrap();
 
+        // Whether the user has voted for it, if their votes were fetched.
+        if let Some(voted) = cache.mpr_cache().has_voted(pkgname) {
+            write!(
+                return_string,
+                "\n{} {}",
+                "Voted:".bold(),
+                if voted { "Yes" } else { "No" }
+            )
+            .unwrap();
+        }
+
         // Popularity.
         write!(
             return_string,
@@ -123,22 +145,161 @@ pub fn generate_pkginfo_entry(pkgname: &str, cache: &Cache, name_only: bool) ->
             ood_date = "N/A".to_owned();
         }
 
-        write!(return_string, "\n{} {}", "Popularity:".bold(), ood_date).unwrap();
+        write!(return_string, "\n{} {}", "Out of Date:".bold(), ood_date).unwrap();
     }
 
     return_string
 }
 
-pub fn generate_pkginfo_entries<T: AsRef<str>>(
-    pkgs: &[T],
+/// A package's APT details, for use in machine-readable output.
+#[derive(Serialize)]
+struct AptPkgInfo {
+    candidate_version: Option<String>,
+    installed_version: Option<String>,
+    description: Option<String>,
+}
+
+/// A package's details, for use in machine-readable output.
+#[derive(Serialize)]
+struct PkgInfo<'a> {
+    name: &'a str,
+    sources: Vec<&'a str>,
+    installed: bool,
+    apt: Option<AptPkgInfo>,
+    mpr: Option<&'a MprPackage>,
+    /// Whether the user has voted for the package, if their votes were
+    /// fetched.
+    voted: Option<bool>,
+}
+
+impl<'a> PkgInfo<'a> {
+    fn new(pkgname: &'a str, cache: &'a Cache) -> Self {
+        let apt_pkg = cache.apt_cache().get(pkgname);
+        let mpr_pkg = cache.mpr_cache().packages().get(pkgname);
+
+        let mut sources = vec![];
+
+        if apt_pkg.is_some() {
+            sources.push("APT");
+        }
+        // Like in the text output, packages from other MPR-compatible repositories
+        // are listed by the name of their repository.
+        if let Some(mpr_pkg) = mpr_pkg {
+            if mpr_pkg.repo == MPR_REPO_NAME {
+                sources.push("MPR");
+            } else {
+                sources.push(mpr_pkg.repo.as_str());
+            }
+        }
+
+        let installed = apt_pkg.as_ref().is_some_and(|pkg| pkg.is_installed());
+        let apt = apt_pkg.map(|pkg| {
+            let candidate = pkg.candidate();
+
+            AptPkgInfo {
+                candidate_version: candidate.as_ref().map(|version| version.version()),
+                installed_version: pkg.installed().map(|version| version.version()),
+                description: candidate.and_then(|version| version.description()),
+            }
+        });
+
+        Self {
+            name: pkgname,
+            sources,
+            installed,
+            apt,
+            mpr: mpr_pkg,
+            voted: cache.mpr_cache().has_voted(pkgname),
+        }
+    }
+}
+
+/// Generate a JSON array containing the details of each package.
+fn generate_pkginfo_json(pkgnames: &[&str], cache: &Cache) -> String {
+    let pkgs: Vec<PkgInfo> = pkgnames
+        .iter()
+        .map(|pkgname| PkgInfo::new(pkgname, cache))
+        .collect();
+
+    serde_json::to_string(&pkgs).unwrap() + "\n"
+}
+
+/// Generate tab-separated values containing the details of each package, with
+/// a header line naming each column.
+fn generate_pkginfo_tsv(pkgnames: &[&str], cache: &Cache) -> String {
+    // Tabs and newlines would break up the columns and rows, so swap them out.
+    let clean = |string: &str| string.replace(['\t', '\n'], " ");
+    let mut result_string = [
+        "name",
+        "sources",
+        "installed",
+        "apt_candidate_version",
+        "apt_installed_version",
+        "mpr_version",
+        "mpr_pkgbase",
+        "mpr_maintainer",
+        "mpr_votes",
+        "mpr_popularity",
+        "mpr_out_of_date",
+        "mpr_repository",
+        "mpr_voted",
+        "description",
+    ]
+    .join("\t");
+    result_string.push('\n');
+
+    for pkgname in pkgnames {
+        let info = PkgInfo::new(pkgname, cache);
+        let apt = info.apt.as_ref();
+        let mpr = info.mpr;
+        let description = apt
+            .and_then(|apt| apt.description.clone())
+            .or_else(|| mpr.and_then(|mpr| mpr.pkgdesc.clone()))
+            .unwrap_or_default();
+
+        let columns = [
+            info.name.to_string(),
+            info.sources.join(","),
+            info.installed.to_string(),
+            apt.and_then(|apt| apt.candidate_version.clone())
+                .unwrap_or_default(),
+            apt.and_then(|apt| apt.installed_version.clone())
+                .unwrap_or_default(),
+            mpr.map(|mpr| mpr.version.clone()).unwrap_or_default(),
+            mpr.map(|mpr| mpr.pkgbase.clone()).unwrap_or_default(),
+            mpr.and_then(|mpr| mpr.maintainer.clone())
+                .unwrap_or_default(),
+            mpr.map(|mpr| mpr.num_votes.to_string()).unwrap_or_default(),
+            mpr.map(|mpr| mpr.popularity.to_string())
+                .unwrap_or_default(),
+            mpr.and_then(|mpr| mpr.ood)
+                .map(|ood| ood.to_string())
+                .unwrap_or_default(),
+            mpr.map(|mpr| mpr.repo.clone()).unwrap_or_default(),
+            info.voted
+                .map(|voted| voted.to_string())
+                .unwrap_or_default(),
+            description,
+        ];
+
+        let columns: Vec<String> = columns.iter().map(|column| clean(column)).collect();
+        result_string.push_str(&columns.join("\t"));
+        result_string.push('\n');
+    }
+
+    result_string
+}
+
+/// Filter a list of packages down to the ones that pass the given filters.
+pub fn filter_pkgs<'a, T: AsRef<str>>(
+    pkgs: &'a [T],
     cache: &Cache,
     apt_only: bool,
     mpr_only: bool,
     installed_only: bool,
-    name_only: bool,
-) -> String {
+    voted_only: bool,
+) -> Vec<&'a str> {
     let mut matches = Vec::new();
-    let mut result_string = String::new();
 
     for pkg in pkgs {
         let pkgname = pkg.as_ref();
@@ -163,17 +324,42 @@ pub fn generate_pkginfo_entries<T: AsRef<str>>(
             continue;
         }
 
+        // Voted only.
+        if voted_only && cache.mpr_cache().has_voted(pkgname) != Some(true) {
+            continue;
+        }
+
         // Package be passed all the tests bro. We's be adding it to the vector now.
         matches.push(pkgname);
     }
 
-    let matches_len = matches.len();
+    matches
+}
+
+/// Generate the output for a list of packages.
+/// `output` is the format to generate it in, being one of `text`, `json`, or
+/// `tsv`.
+pub fn format_pkginfo_entries(
+    pkgnames: &[&str],
+    cache: &Cache,
+    name_only: bool,
+    output: &str,
+) -> String {
+    let mut result_string = String::new();
+
+    match output {
+        "json" => return generate_pkginfo_json(pkgnames, cache),
+        "tsv" => return generate_pkginfo_tsv(pkgnames, cache),
+        _ => (),
+    }
+
+    let pkgnames_len = pkgnames.len();
 
-    for (index, pkgname) in matches.iter().enumerate() {
+    for (index, pkgname) in pkgnames.iter().enumerate() {
         if name_only {
             result_string.push_str(pkgname);
             result_string.push('\n');
-        } else if index == matches_len - 1 {
+        } else if index == pkgnames_len - 1 {
             result_string.push_str(&generate_pkginfo_entry(pkgname, cache, name_only));
             result_string.push('\n');
         } else {
@@ -1,72 +1,344 @@
-use crate::{cache::MprCache, message, progress::MistAcquireProgress, style::Colorize, util};
+use crate::{
+    cache::{MprPackage, MprRepoCache},
+    config::{Config, MprRepository, CONFIG},
+    deb, message,
+    progress::{MistAcquireProgress, MistDownloadProgress},
+    style::Colorize,
+    util,
+};
 use makedeb_srcinfo::SplitDependency;
-use rust_apt::{cache::Cache as AptCache, progress::AcquireProgress, tagfile::TagSection};
+use reqwest::{
+    blocking::{Client, Response},
+    header::{self, HeaderName},
+    StatusCode,
+};
+use rust_apt::{cache::Cache as AptCache, progress::AcquireProgress};
+use serde::{Deserialize, Serialize};
 use std::{
-    env, fs,
-    io::{self, Write},
-    path,
-    process::Command,
+    env,
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+    thread,
 };
 
-pub fn update(args: &clap::ArgMatches) {
-    let mpr_url: &String = args.get_one("mpr-url").unwrap();
+/// The file marking a repository's metadata as coming from the system-wide
+/// configuration file.
+const SYSTEM_REPO_MARKER: &str = ".system";
+
+/// The HTTP headers used to tell if a downloaded file has changed on the
+/// server since it was last fetched.
+#[derive(Deserialize, Serialize)]
+struct CacheHeaders {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
 
-    // For some reason we have to set our current UID to 0 instead of just the EUID
-    // when using setuid functionality. TODO: No clue why, but this fixes the
-    // issue for now.
-    users::switch::set_current_uid(0).unwrap();
+impl CacheHeaders {
+    fn from_response(url: &str, resp: &Response) -> Self {
+        let get_header = |name: HeaderName| {
+            resp.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_owned())
+        };
 
-    // Update APT packages.
-    let cache = AptCache::new();
-    let mut progress: Box<dyn AcquireProgress> = Box::new(MistAcquireProgress {});
+        Self {
+            url: url.to_owned(),
+            etag: get_header(header::ETAG),
+            last_modified: get_header(header::LAST_MODIFIED),
+        }
+    }
 
-    if let Err(error) = cache.update(&mut progress) {
-        for msg in error.what().split(';') {
-            if msg.starts_with("E:") {
-                message::error(&format!("{}\n", msg.strip_prefix("E:").unwrap()));
-            } else if msg.starts_with("W:") {
-                message::warning(&format!("{}\n", msg.strip_prefix("W:").unwrap()));
-            };
+    /// Get the path the headers for `file` are stored at.
+    fn path(file: &Path) -> PathBuf {
+        let mut path = file.as_os_str().to_owned();
+        path.push(".headers");
+        PathBuf::from(path)
+    }
+
+    /// Read the headers saved for `file`, as long as they were saved for the
+    /// same URL.
+    fn load(file: &Path, url: &str) -> Option<Self> {
+        let headers: Self =
+            serde_json::from_str(&fs::read_to_string(Self::path(file)).ok()?).ok()?;
+        (headers.url == url).then_some(headers)
+    }
+
+    fn save(&self, file: &Path) -> io::Result<()> {
+        fs::write(Self::path(file), serde_json::to_string(self).unwrap())
+    }
+
+    fn remove(file: &Path) -> io::Result<()> {
+        match fs::remove_file(Self::path(file)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
         }
-    };
+    }
+}
 
-    // Get the new MPR pkglist.
-    let client = reqwest::blocking::Client::new();
-    match client.get(format!("{}/packages.gz", mpr_url)).send() {
-        Ok(resp) => {
-            let mut cache_dir = util::xdg::get_global_cache_dir();
-            cache_dir.push("pkglist.gz");
-            util::fs::create_file(&cache_dir.clone().into_os_string().into_string().unwrap());
-            fs::write(&cache_dir, resp.bytes().unwrap()).unwrap();
+/// Download `url` to `path`, skipping the download if the file hasn't changed
+/// since it was last fetched, and resuming it if a previous one got
+/// interrupted.
+///
+/// Returns the headers to save for the file once the caller is done processing
+/// it, or [`None`] if it hasn't changed.
+fn download(client: &Client, url: &str, path: &Path) -> io::Result<Option<CacheHeaders>> {
+    let mut part_path = path.as_os_str().to_owned();
+    part_path.push(".part");
+    let part_path = PathBuf::from(part_path);
+
+    let mut request = client.get(url);
+
+    if path.exists()
+        && let Some(headers) = CacheHeaders::load(path, url)
+    {
+        if let Some(etag) = headers.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
         }
-        Err(err) => {
-            message::error(&format!("Failed to make request [{}]\n", err));
-            quit::with_code(exitcode::UNAVAILABLE);
+        if let Some(last_modified) = headers.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
         }
-    };
+    }
+
+    // Only resume a partial download if the file hasn't changed on the server
+    // since it was started.
+    let mut resume_from = 0;
 
-    // Get the new MPR cache.
-    let resp = match client
-        .get(format!("{}/packages-meta-ext-v2.json.gz", mpr_url))
-        .send()
+    if let Ok(metadata) = fs::metadata(&part_path)
+        && let Some(headers) = CacheHeaders::load(&part_path, url)
+        && let Some(validator) = headers.etag.or(headers.last_modified)
     {
-        Ok(resp) => resp.bytes().unwrap(),
+        resume_from = metadata.len();
+        request = request
+            .header(header::RANGE, format!("bytes={}-", resume_from))
+            .header(header::IF_RANGE, validator);
+    }
+
+    let mut resp = request.send().map_err(io::Error::other)?;
+
+    match resp.status() {
+        StatusCode::NOT_MODIFIED => {
+            println!("{} {}", "Hit:".green().bold(), url);
+            return Ok(None);
+        }
+        StatusCode::PARTIAL_CONTENT => (),
+        status if status.is_success() => resume_from = 0,
+        status => {
+            return Err(io::Error::other(format!(
+                "the server returned '{}'",
+                status
+            )))
+        }
+    }
+
+    let headers = CacheHeaders::from_response(url, &resp);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resume_from != 0)
+        .truncate(resume_from == 0)
+        .open(&part_path)?;
+    headers.save(&part_path)?;
+
+    let mut progress = MistDownloadProgress::new(
+        url.to_owned(),
+        resume_from,
+        resp.content_length().map(|len| len + resume_from),
+    );
+    let mut buf = [0; 8192];
+
+    loop {
+        let num_read = resp.read(&mut buf)?;
+
+        if num_read == 0 {
+            break;
+        }
+
+        file.write_all(&buf[..num_read])?;
+        progress.inc(num_read as u64);
+    }
+
+    progress.finish();
+
+    // The old headers don't apply to the new file, so remove them until the
+    // caller saves the new ones.
+    CacheHeaders::remove(path)?;
+    fs::rename(&part_path, path)?;
+    CacheHeaders::remove(&part_path)?;
+
+    Ok(Some(headers))
+}
+
+/// Download a file via [`download`], aborting if unable to do so.
+fn download_or_quit(client: &Client, url: &str, path: &Path) -> Option<CacheHeaders> {
+    match download(client, url, path) {
+        Ok(headers) => headers,
         Err(err) => {
-            message::error(&format!("Failed to make request [{}]\n", err));
+            message::error(&format!(
+                "Failed to download '{}' [{}]\n",
+                url.bold().green(),
+                err.to_string().bold()
+            ));
             quit::with_code(exitcode::UNAVAILABLE);
         }
-    };
+    }
+}
+
+/// Save the headers for a file that was just downloaded, so that the next
+/// update can skip the download if the file doesn't change.
+fn save_headers(headers: &CacheHeaders, path: &Path) {
+    if let Err(err) = headers.save(path) {
+        message::warning(&format!(
+            "Failed to save download information for '{}' [{}]\n",
+            path.display().to_string().bold().green(),
+            err.to_string().bold()
+        ));
+    }
+}
+
+/// Generate the control file for an MPR package's placeholder '.deb'.
+fn generate_control_file(pkg: &MprPackage, system_distro: &str, system_arch: &str) -> String {
+    let mut control_file_str = String::new();
+    control_file_str.push_str(&format!("Package: {}\n", pkg.pkgname));
+    control_file_str.push_str(&format!("Version: {}\n", pkg.version));
+    control_file_str.push_str("Architecture: all\n");
+    control_file_str.push_str("Description: Dummy description so 'dpkg-deb' doesn't complain.\n");
+
+    let mut depends = vec![];
+    let mut predepends = vec![];
+
+    for dep_group in [
+        pkg.get_system_depends(system_distro, system_arch),
+        pkg.get_system_makedepends(system_distro, system_arch),
+        pkg.get_system_checkdepends(system_distro, system_arch),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        for dep in dep_group {
+            if let Some(no_prefix_string) = dep.strip_prefix("p!") {
+                predepends.push(no_prefix_string.to_string());
+            } else {
+                depends.push(dep);
+            }
+        }
+    }
+
+    if !depends.is_empty() {
+        let mut depends_items = String::new();
+        for dep in depends {
+            depends_items.push_str(&SplitDependency::new(&dep).as_control());
+            depends_items.push_str(", ");
+        }
+        depends_items.pop().unwrap();
+        depends_items.pop().unwrap();
+
+        control_file_str.push_str(&format!("Depends: {}\n", &depends_items));
+    }
+
+    if !predepends.is_empty() {
+        let mut predepends_items = String::new();
+        for predep in predepends {
+            predepends_items.push_str(&SplitDependency::new(&predep).as_control());
+            predepends_items.push_str(", ");
+        }
+        predepends_items.pop().unwrap();
+        predepends_items.pop().unwrap();
+
+        control_file_str.push_str(&format!("Pre-Depends: {}\n", &predepends_items));
+    }
+
+    if let Some(conflicts) = pkg.get_system_conflicts(system_distro, system_arch) {
+        let mut conflicts_items = String::new();
+
+        for conflict in conflicts {
+            conflicts_items.push_str(&SplitDependency::new(&conflict).as_control());
+            conflicts_items.push_str(", ");
+        }
+        conflicts_items.pop().unwrap();
+        conflicts_items.pop().unwrap();
+
+        control_file_str.push_str(&format!("Conflicts: {}\n", &conflicts_items));
+    }
 
-    let mpr_cache = match MprCache::validate_data(&resp) {
-        Ok(mpr_cache) => mpr_cache,
-        Err(_) => {
-            message::error("There was an issue validating the downloaded MPR cache archive.");
+    if let Some(provides) = pkg.get_system_provides(system_distro, system_arch) {
+        let mut provides_items = String::new();
+
+        for provide in provides {
+            provides_items.push_str(&SplitDependency::new(&provide).as_control());
+            provides_items.push_str(", ");
+        }
+        provides_items.pop().unwrap();
+        provides_items.pop().unwrap();
+
+        control_file_str.push_str(&format!("Provides: {}\n", &provides_items));
+    }
+
+    control_file_str
+}
+
+/// Write an MPR package's control file and placeholder '.deb' into the current
+/// directory.
+fn build_deb(pkgname: &str, control_file_str: &str) -> io::Result<()> {
+    let control_file_dir = format!("{}/DEBIAN", pkgname);
+    fs::create_dir_all(&control_file_dir)?;
+    fs::write(format!("{}/control", control_file_dir), control_file_str)?;
+    deb::write_control_only_deb(Path::new(&format!("{}.deb", pkgname)), control_file_str)
+}
+
+/// Update the metadata and placeholder packages of an MPR repository.
+fn update_repo(client: &Client, repo: &MprRepository) {
+    let repo_cache_dir = repo.cache_dir();
+    util::fs::create_dir(
+        &repo_cache_dir
+            .clone()
+            .into_os_string()
+            .into_string()
+            .unwrap(),
+    );
+
+    // Get the new MPR pkglist.
+    let pkglist_path = repo_cache_dir.join("pkglist.gz");
+
+    if let Some(headers) =
+        download_or_quit(client, &format!("{}/packages.gz", repo.url), &pkglist_path)
+    {
+        save_headers(&headers, &pkglist_path);
+    }
+
+    // Get the new MPR cache. If it hasn't changed, neither have the packages built
+    // from it.
+    let cache_path = repo_cache_dir.join("cache.gz");
+    let cache_headers = download_or_quit(
+        client,
+        &format!("{}/packages-meta-ext-v2.json.gz", repo.url),
+        &cache_path,
+    );
+
+    if cache_headers.is_none() && MprRepoCache::has_index(repo) {
+        return;
+    }
+
+    let mpr_cache = match fs::read(&cache_path)
+        .ok()
+        .and_then(|data| MprRepoCache::validate_data(repo, &data).ok())
+    {
+        Some(mpr_cache) => mpr_cache,
+        None => {
+            fs::remove_file(&cache_path).ok();
+            message::error(&format!(
+                "There was an issue validating the downloaded cache archive for repository '{}'.\n",
+                repo.name.bold().green()
+            ));
             quit::with_code(exitcode::UNAVAILABLE);
         }
     };
 
     // Create the '.deb' files for the packages in the MPR cache.
-    let mut cache_dir = util::xdg::get_global_cache_dir();
+    let mut cache_dir = repo_cache_dir.clone();
     cache_dir.push("deb-pkgs");
 
     {
@@ -78,158 +350,196 @@ pub fn update(args: &clap::ArgMatches) {
 
     let (system_distro, system_arch) = util::get_distro_arch_info();
 
-    // Get the list of packages we need to build.
-    let mut to_build: Vec<String> = vec![];
+    // Remove any packages that are no longer in the MPR, so that APT doesn't
+    // keep seeing them.
+    for entry in fs::read_dir(&cache_dir).unwrap() {
+        let entry = entry.unwrap();
+        let file_name = entry.file_name().into_string().unwrap();
+        let pkgname = file_name.strip_suffix(".deb").unwrap_or(&file_name);
 
-    for pkg in mpr_cache.packages().values() {
-        // If the deb doesn't exist, we have to build.
-        if !path::Path::new(&format!("{}.deb", pkg.pkgname)).exists() {
-            to_build.push(pkg.pkgname.clone());
+        if mpr_cache.packages().contains_key(pkgname) {
             continue;
         }
 
-        let mut control_file_path = cache_dir.clone();
-        control_file_path.push(&pkg.pkgname);
-        control_file_path.push("DEBIAN");
-        control_file_path.push("control");
+        let result = if entry.file_type().unwrap().is_dir() {
+            fs::remove_dir_all(entry.path())
+        } else {
+            fs::remove_file(entry.path())
+        };
 
-        if path::Path::new(&control_file_path).exists() {
-            let control_file =
-                TagSection::new(&fs::read_to_string(&control_file_path).unwrap()).unwrap();
+        if let Err(err) = result {
+            message::warning(&format!(
+                "Failed to remove stale MPR package '{}' [{}]\n",
+                pkgname.bold().green(),
+                err.to_string().bold()
+            ));
+        }
+    }
 
-            // If the version in the control file matches the current MPR package's version,
-            // then we don't need to update it.
-            if control_file.get("Version").unwrap() == &pkg.version {
-                continue;
-            } else {
-                to_build.push(pkg.pkgname.clone());
-            }
+    // Get the list of packages we need to build, along with their control files.
+    let mut to_build: Vec<(&String, String)> = vec![];
+
+    for pkg in mpr_cache.packages().values() {
+        let control_file_str = generate_control_file(pkg, &system_distro, &system_arch);
+
+        // If the deb exists and its control file is unchanged, then we don't need to
+        // update it. Comparing the whole control file (instead of just the version)
+        // means packages also get rebuilt when their dependencies change.
+        if Path::new(&format!("{}.deb", pkg.pkgname)).exists()
+            && let Ok(current_control_file_str) =
+                fs::read_to_string(format!("{}/DEBIAN/control", pkg.pkgname))
+            && current_control_file_str == control_file_str
+        {
+            continue;
         }
+
+        to_build.push((&pkg.pkgname, control_file_str));
     }
 
     let num_of_packages = to_build.len();
+    let num_processed = AtomicUsize::new(0);
+    let num_threads = thread::available_parallelism().map_or(1, |num| num.get());
 
-    for (iter, pkg_string) in to_build.iter().enumerate() {
-        let pkg = mpr_cache.packages().get(pkg_string).unwrap();
-
-        // Generate the control file.
-        let mut control_file_str = String::new();
-        control_file_str.push_str(&format!("Package: {}\n", pkg.pkgname));
-        control_file_str.push_str(&format!("Version: {}\n", pkg.version));
-        control_file_str.push_str("Architecture: all\n");
-        control_file_str
-            .push_str("Description: Dummy description so 'dpkg-deb' doesn't complain.\n");
-
-        let mut depends = vec![];
-        let mut predepends = vec![];
-
-        for dep_group in [
-            pkg.get_system_depends(&system_distro, &system_arch),
-            pkg.get_system_makedepends(&system_distro, &system_arch),
-            pkg.get_system_checkdepends(&system_distro, &system_arch),
-        ]
-        .into_iter()
-        .flatten()
-        {
-            for dep in dep_group {
-                if let Some(no_prefix_string) = dep.strip_prefix("p!") {
-                    predepends.push(no_prefix_string.to_string());
-                } else {
-                    depends.push(dep);
+    let clear_line = || {
+        print!("\x1b[2K");
+        io::stdout().flush().unwrap();
+        print!("\x1b[0G");
+        io::stdout().flush().unwrap();
+    };
+
+    // Build the packages, spreading them out across each CPU core.
+    let num_processed = &num_processed;
+
+    thread::scope(|scope| {
+        for chunk in to_build.chunks(num_of_packages / num_threads + 1) {
+            scope.spawn(move || {
+                for (pkgname, control_file_str) in chunk {
+                    let result = build_deb(pkgname, control_file_str);
+                    let iter = num_processed.fetch_add(1, AtomicOrdering::Relaxed);
+
+                    // Hold onto stdout so that other threads' output doesn't get mixed in.
+                    let _stdout = io::stdout().lock();
+                    clear_line();
+
+                    if let Err(err) = result {
+                        message::error(&format!(
+                            "Failed to process MPR package '{}'. The package won't be available to install from the MPR. [{}]\n",
+                            pkgname.bold().green(),
+                            err.to_string().bold()
+                        ));
+                    }
+
+                    message::info(&format!(
+                        "[{}/{}] Processing MPR package '{}'...",
+                        iter + 1,
+                        num_of_packages,
+                        pkgname.bold().green(),
+                    ));
+                    io::stdout().flush().unwrap();
                 }
-            }
+            });
         }
+    });
 
-        if !depends.is_empty() {
-            let mut depends_items = String::new();
-            for dep in depends {
-                depends_items.push_str(&SplitDependency::new(&dep).as_control());
-                depends_items.push_str(", ");
-            }
-            depends_items.pop().unwrap();
-            depends_items.pop().unwrap();
+    println!();
 
-            control_file_str.push_str(&format!("Depends: {}\n", &depends_items));
-        }
+    if let Err(err) = mpr_cache.write_index() {
+        message::error(&format!(
+            "Failed to write the cache for repository '{}' [{}]\n",
+            repo.name.bold().green(),
+            err.to_string().bold()
+        ));
+        quit::with_code(exitcode::UNAVAILABLE);
+    }
 
-        if !predepends.is_empty() {
-            let mut predepends_items = String::new();
-            for predep in predepends {
-                predepends_items.push_str(&SplitDependency::new(&predep).as_control());
-                predepends_items.push_str(", ");
-            }
-            predepends_items.pop().unwrap();
-            predepends_items.pop().unwrap();
+    // Only save the cache's headers now that every package has been built, so that
+    // an interrupted update gets picked back up on the next run.
+    if let Some(cache_headers) = cache_headers {
+        save_headers(&cache_headers, &cache_path);
+    }
+}
 
-            control_file_str.push_str(&format!("Pre-Depends: {}\n", &predepends_items));
-        }
+/// Remove the metadata of repositories that have been removed from the
+/// system-wide configuration file.
+///
+/// Repositories from users' own configuration files are never removed here, as
+/// other users could still be using them.
+fn remove_stale_repos(system_repos: &[MprRepository]) {
+    let mut repos_dir = util::xdg::get_global_cache_dir();
+    repos_dir.push("repos");
 
-        if let Some(conflicts) = pkg.get_system_conflicts(&system_distro, &system_arch) {
-            let mut conflicts_items = String::new();
+    for entry in fs::read_dir(&repos_dir).into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
 
-            for conflict in conflicts {
-                conflicts_items.push_str(&SplitDependency::new(&conflict).as_control());
-                conflicts_items.push_str(", ");
-            }
-            conflicts_items.pop().unwrap();
-            conflicts_items.pop().unwrap();
+        if !entry.path().join(SYSTEM_REPO_MARKER).exists()
+            || system_repos.iter().any(|repo| repo.name == name)
+        {
+            continue;
+        }
 
-            control_file_str.push_str(&format!("Conflicts: {}\n", &conflicts_items));
+        if let Err(err) = fs::remove_dir_all(entry.path()) {
+            message::warning(&format!(
+                "Failed to remove the metadata of old repository '{}' [{}]\n",
+                name.bold().green(),
+                err.to_string().bold()
+            ));
         }
+    }
+}
 
-        if let Some(provides) = pkg.get_system_provides(&system_distro, &system_arch) {
-            let mut provides_items = String::new();
+pub fn update(args: &clap::ArgMatches) {
+    let mpr_url: &String = args.get_one("mpr-url").unwrap();
 
-            for provide in provides {
-                provides_items.push_str(&SplitDependency::new(&provide).as_control());
-                provides_items.push_str(", ");
-            }
-            provides_items.pop().unwrap();
-            provides_items.pop().unwrap();
+    // For some reason we have to set our current UID to 0 instead of just the EUID
+    // when using setuid functionality. TODO: No clue why, but this fixes the
+    // issue for now.
+    users::switch::set_current_uid(0).unwrap();
 
-            control_file_str.push_str(&format!("Provides: {}\n", &provides_items));
+    // Update APT packages.
+    let cache = AptCache::new();
+    let mut progress: Box<dyn AcquireProgress> = Box::new(MistAcquireProgress {});
+
+    if let Err(error) = cache.update(&mut progress) {
+        for msg in error.what().split(';') {
+            if msg.starts_with("E:") {
+                message::error(&format!("{}\n", msg.strip_prefix("E:").unwrap()));
+            } else if msg.starts_with("W:") {
+                message::warning(&format!("{}\n", msg.strip_prefix("W:").unwrap()));
+            };
         }
+    };
+
+    let client = Client::new();
+    let repos = CONFIG.repositories(mpr_url);
+    let system_repos = Config::system_repositories();
+
+    for repo in &repos {
+        update_repo(&client, repo);
+
+        // Mark the repositories from the system-wide configuration file, so that
+        // they can be found to be stale once they're removed from it.
+        let marker_path = repo.cache_dir().join(SYSTEM_REPO_MARKER);
 
-        // Write the control file.
-        let control_file_dir = pkg.pkgname.clone() + "/DEBIAN";
-        util::fs::create_dir(&control_file_dir);
-        let mut control_file = util::fs::create_file(&(control_file_dir.clone() + "/control"));
-        control_file.write_all(control_file_str.as_bytes()).unwrap();
+        let is_system_repo = system_repos
+            .iter()
+            .any(|system_repo| system_repo.name == repo.name);
 
-        // Build the package.
-        let clear_line = || {
-            print!("\x1b[2K");
-            io::stdout().flush().unwrap();
-            print!("\x1b[0G");
-            io::stdout().flush().unwrap();
+        let result = if is_system_repo {
+            fs::write(&marker_path, "")
+        } else if marker_path.exists() {
+            fs::remove_file(&marker_path)
+        } else {
+            Ok(())
         };
-        clear_line();
-        message::info(&format!(
-            "[{}/{}] Processing MPR package '{}'...",
-            iter + 1,
-            num_of_packages,
-            pkg.pkgname.bold().green(),
-        ));
-        io::stdout().flush().unwrap();
 
-        let cmd = Command::new("dpkg-deb")
-            .args(["-b", &pkg.pkgname])
-            .output()
-            .unwrap();
-        if !cmd.status.success() {
-            clear_line();
-            message::error(&format!(
-                "Failed to process MPR package '{}'. The package won't be available to install from the MPR.\n",
-                pkg.pkgname.bold().green()
+        if let Err(err) = result {
+            message::warning(&format!(
+                "Failed to update '{}' [{}]\n",
+                marker_path.display().to_string().bold().green(),
+                err.to_string().bold()
             ));
         }
     }
 
-    println!();
-
-    // Write the archive file.
-    cache_dir.pop();
-    cache_dir.push("cache.gz");
-    util::fs::create_file(&cache_dir.clone().into_os_string().into_string().unwrap());
-    fs::write(&cache_dir, resp).unwrap();
+    remove_stale_repos(&system_repos);
 }
@@ -1,5 +1,6 @@
 use crate::{
-    cache::{Cache, MprCache},
+    cache::{Cache, CommitOptions, MprCache},
+    hold::Pins,
     install_util, util,
 };
 use rust_apt::{
@@ -12,6 +13,7 @@ pub fn upgrade(args: &clap::ArgMatches) {
     let apt_only = args.is_present("apt-only");
     let mpr_only = args.is_present("mpr-only");
     let mpr_url: &String = args.get_one("mpr-url").unwrap();
+    let options = CommitOptions::from_args(args);
 
     let cache = Cache::new(AptCache::new(), MprCache::new());
 
@@ -26,25 +28,71 @@ pub fn upgrade(args: &clap::ArgMatches) {
         dpkg_map.insert(pkg.get("Package").unwrap().to_owned(), pkg);
     }
 
+    // Packages that are held via dpkg selections, or pinned via `mist pin`, don't
+    // get upgraded.
+    let pins = Pins::load();
+    let is_held = |pkgname: &str| {
+        dpkg_map
+            .get(pkgname)
+            .and_then(|pkg_control| pkg_control.get("Status"))
+            .is_some_and(|status| status.starts_with("hold "))
+    };
+
     // The list of MPR packages we're going to update.
     let mut mpr_pkgs = vec![];
 
+    // The list of packages that have an upgrade available, but were held back.
+    let mut held_back = vec![];
+
     // Check which APT packages need upgrading, and mark any for such if needed.
     for pkg in Cache::get_nonvirtual_packages(cache.apt_cache(), &PackageSort::default()) {
         let pkgname = pkg.name();
 
-        if !mpr_only && pkg.is_upgradable(false) && let Some(pkg_control) = dpkg_map.get(&pkgname) && pkg_control.get("MPR-Package").is_none() {
+        if !mpr_only
+            && pkg.is_upgradable(false)
+            && let Some(pkg_control) = dpkg_map.get(&pkgname)
+            && pkg_control.get("MPR-Package").is_none()
+        {
+            // Pinned packages can only move to their pinned version, so hold them back
+            // if it's unavailable or already installed.
+            let pin = pins.get(&pkgname);
+            let pinned_version = pin.and_then(|pin| pkg.get_version(pin));
+            let pin_installed = pin.is_some_and(|pin| {
+                pkg.installed()
+                    .is_some_and(|installed| installed.version() == *pin)
+            });
+            let pin_held_back = pin.is_some() && (pinned_version.is_none() || pin_installed);
+
+            if is_held(&pkgname) || pin_held_back {
+                held_back.push(format!("apt/{}", pkgname));
+                continue;
+            }
+
+            if let Some(version) = pinned_version {
+                version.set_candidate();
+            }
+
             pkg.mark_install(false, !pkg.is_auto_installed());
             pkg.protect();
-        } else if !apt_only && let Some(pkg_control) = dpkg_map.get(&pkgname) && pkg_control.get("MPR-Package").is_some() {
-            // See if the MPR version is more recent. If so, add the package for installation.
-            if crate::apt_util::cmp_versions(
-                dpkg_map.get(&pkgname).unwrap().get("Version").unwrap(),
-                &cache.mpr_cache().packages().get(&pkgname).unwrap().version,
-            )
-            .is_lt()
+        } else if !apt_only
+            && let Some(pkg_control) = dpkg_map.get(&pkgname)
+            && pkg_control.get("MPR-Package").is_some()
+            && let Some(mpr_pkg) = cache.mpr_cache().packages().get(&pkgname)
+        {
+            // See if the MPR version is more recent. If so, add the package for
+            // installation.
+            if crate::apt_util::cmp_versions(pkg_control.get("Version").unwrap(), &mpr_pkg.version)
+                .is_lt()
             {
-                mpr_pkgs.push(pkgname);
+                if is_held(&pkgname)
+                    || pins
+                        .get(&pkgname)
+                        .is_some_and(|pin| *pin != mpr_pkg.version)
+                {
+                    held_back.push(format!("mpr/{}", pkgname));
+                } else {
+                    mpr_pkgs.push(pkgname);
+                }
             }
         }
     }
@@ -61,6 +109,5 @@ pub fn upgrade(args: &clap::ArgMatches) {
         quit::with_code(exitcode::UNAVAILABLE);
     }
 
-    crate::message::warning(&format!("{}\n", mpr_install_order.len()));
-    cache.commit(&mpr_install_order, mpr_url);
+    cache.commit(&mpr_install_order, mpr_url, &options, &held_back);
 }
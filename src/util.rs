@@ -1,4 +1,4 @@
-use crate::{apt_util, message, style::Colorize};
+use crate::{apt_util, config::CONFIG, message, style::Colorize};
 use core::fmt::Display;
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 use std::{
     ffi::OsStr,
     fs as std_fs,
-    io::{self, Write},
+    io::{self, Read, Write},
     path,
     process::{Command as ProcCommand, ExitStatus},
     str,
@@ -17,6 +17,7 @@ struct AuthenticationError {
     #[serde(rename = "type")]
     pub resp_type: String,
     pub code: String,
+    pub msg: Option<String>,
 }
 
 // Struct to handle API-authenticated requests to the MPR.
@@ -55,17 +56,31 @@ impl<'a> AuthenticatedRequest<'a> {
         resp_text
     }
 
-    pub fn get(&self, path: &str) -> String {
-        // Make the request.
+    fn send_get(&self, path: &str) -> reqwest::Result<reqwest::blocking::Response> {
         let client = reqwest::blocking::Client::new();
-        let resp = client
+        client
             .get(format!("{}/api/{}", self.mpr_url, path))
             .header("Authorization", self.api_token)
-            .send();
+            .send()
+    }
 
+    pub fn get(&self, path: &str) -> String {
+        // Make the request.
+        let resp = self.send_get(path);
         self.handle_response(resp)
     }
 
+    /// Make a GET request, returning [`None`] instead of aborting if it
+    /// couldn't be made or the MPR returned an error.
+    pub fn try_get(&self, path: &str) -> Option<String> {
+        let resp_text = self.send_get(path).ok()?.text().ok()?;
+
+        match serde_json::from_str::<AuthenticationError>(&resp_text) {
+            Ok(json) if json.resp_type == "error" => None,
+            _ => Some(resp_text),
+        }
+    }
+
     pub fn post(&self, path: &str, body: String) -> String {
         // Make the request.
         let client = reqwest::blocking::Client::new();
@@ -77,6 +92,79 @@ impl<'a> AuthenticatedRequest<'a> {
 
         self.handle_response(resp)
     }
+
+    /// Make a POST request whose response isn't otherwise looked at, aborting
+    /// if the MPR returned an error instead of carrying it out.
+    pub fn post_checked(&self, path: &str, body: String) -> String {
+        let resp_text = self.post(path, body);
+
+        if let Ok(json) = serde_json::from_str::<AuthenticationError>(&resp_text)
+            && json.resp_type == "error"
+        {
+            message::error(&format!(
+                "The MPR couldn't complete the request [{}]\n",
+                json.msg.unwrap_or(json.code).bold()
+            ));
+            quit::with_code(exitcode::UNAVAILABLE);
+        }
+
+        resp_text
+    }
+}
+
+/// Get the API token passed to a command, aborting if there isn't one.
+pub fn get_api_token(args: &clap::ArgMatches) -> &String {
+    match args.get_one("token") {
+        Some(token) => token,
+        None => {
+            message::error("No API token was provided.\n");
+            quit::with_code(exitcode::USAGE);
+        }
+    }
+}
+
+/// Have the user write a message in their editor, returning what they wrote.
+/// `what` describes the message, i.e. `a comment`.
+pub fn edit_message(what: &str) -> String {
+    // Get the editor.
+    let editor = match CONFIG.get_editor() {
+        Ok(editor) => editor.into_os_string().into_string().unwrap(),
+        Err(err) => {
+            message::error(&format!(
+                "Couldn't find an editor to write {} with. [{}]\n",
+                what, err
+            ));
+
+            quit::with_code(exitcode::UNAVAILABLE);
+        }
+    };
+
+    // Generate a temporary file to write the message in.
+    let file = match tempfile::Builder::new().suffix(".md").tempfile_in("/tmp") {
+        Ok(file) => file.path().to_str().unwrap().to_owned(),
+        Err(err) => {
+            message::error(&format!(
+                "Failed to create temporary file to write {} in. [{}]\n",
+                what, err
+            ));
+            quit::with_code(exitcode::UNAVAILABLE);
+        }
+    };
+
+    // Open the file in the editor.
+    message::info(&format!("Opening '{}' in '{}'...\n", &file, editor));
+
+    let mut cmd = sudo::run_as_normal_user(&editor);
+    cmd.arg(&file);
+    let status = cmd.spawn().unwrap().wait().unwrap();
+    check_exit_status(&cmd, &status);
+
+    // Read the changed file.
+    let mut file_content = String::new();
+    let mut _file = std_fs::File::open(file).unwrap();
+    _file.read_to_string(&mut file_content).unwrap();
+
+    file_content
 }
 
 /// Handle errors from APT.
@@ -104,7 +192,7 @@ pub fn check_exit_status(cmd: &ProcCommand, status: &ExitStatus) {
 }
 
 /// Format a list of package names in the way APT would.
-pub fn format_apt_pkglist<T: AsRef<str> + Display>(pkgnames: &Vec<T>) {
+pub fn format_apt_pkglist<T: AsRef<str> + Display>(pkgnames: &[T]) {
     // All package lines always start with two spaces, so pretend like we have two
     // less characters.
     let term_width = apt_util::terminal_width() - 2;
@@ -133,6 +221,15 @@ pub fn is_yes(resp: &str, default: bool) -> bool {
     resp.to_lowercase() == "y" || (resp.is_empty() && default)
 }
 
+/// Get the pager mode for a `paging` value (`auto`, `always` or `never`).
+pub fn paging_mode(paging: &str) -> bat::PagingMode {
+    match paging {
+        "always" => bat::PagingMode::Always,
+        "never" => bat::PagingMode::Never,
+        _ => bat::PagingMode::QuitIfOneScreen,
+    }
+}
+
 /// Print out a question with options and get the result.
 /// `multi_allowed` specifies if only a single option can be chosen.
 pub fn ask_question(question: &str, options: &Vec<&str>, multi_allowed: bool) -> Vec<String> {
@@ -249,9 +346,11 @@ pub fn get_distro_arch_info() -> (String, String) {
 
     let distro = std::str::from_utf8(&distro_cmd.output().unwrap().stdout)
         .unwrap()
+        .trim()
         .to_owned();
     let arch = std::str::from_utf8(&arch_cmd.output().unwrap().stdout)
         .unwrap()
+        .trim()
         .to_owned();
 
     (distro, arch)
@@ -0,0 +1,79 @@
+use crate::{cache::MprCache, message, style::Colorize, util};
+use std::collections::HashSet;
+
+/// Parse the package bases a user has voted for from the MPR's response.
+fn parse_voted_pkgbases(resp_text: &str) -> serde_json::Result<HashSet<String>> {
+    serde_json::from_str(resp_text)
+}
+
+/// Get the package bases the authenticated user has voted for.
+pub fn get_voted_pkgbases(api_token: &str, mpr_url: &str) -> HashSet<String> {
+    let request = util::AuthenticatedRequest::new(api_token, mpr_url);
+    let resp_text = request.get("votes");
+
+    match parse_voted_pkgbases(&resp_text) {
+        Ok(pkgbases) => pkgbases,
+        Err(err) => {
+            message::error(&format!(
+                "Failed to parse the packages you've voted for [{}]\n",
+                err.to_string().bold()
+            ));
+            quit::with_code(exitcode::UNAVAILABLE);
+        }
+    }
+}
+
+/// Fetch the authenticated user's votes into `mpr_cache`, so that they can be
+/// shown alongside packages.
+pub fn load_votes(mpr_cache: &mut MprCache, args: &clap::ArgMatches) {
+    let api_token = util::get_api_token(args);
+    let mpr_url: &String = args.get_one("mpr-url").unwrap();
+
+    mpr_cache.set_voted(get_voted_pkgbases(api_token, mpr_url));
+}
+
+/// Like [`load_votes`], but carry on without the user's votes if they can't be
+/// fetched, such as when the MPR can't be reached.
+pub fn try_load_votes(mpr_cache: &mut MprCache, api_token: &str, mpr_url: &str) {
+    let request = util::AuthenticatedRequest::new(api_token, mpr_url);
+
+    match request
+        .try_get("votes")
+        .and_then(|resp_text| parse_voted_pkgbases(&resp_text).ok())
+    {
+        Some(pkgbases) => mpr_cache.set_voted(pkgbases),
+        None => message::warning(
+            "Failed to fetch the packages you've voted for, so votes won't be shown.\n",
+        ),
+    }
+}
+
+/// Add or remove the authenticated user's vote for a package base.
+fn set_vote(args: &clap::ArgMatches, vote: bool) {
+    let pkgbase: &String = args.get_one("pkg").unwrap();
+    let mpr_url: &String = args.get_one("mpr-url").unwrap();
+    let api_token = util::get_api_token(args);
+
+    MprCache::new().ensure_pkgbase(pkgbase);
+
+    let request = util::AuthenticatedRequest::new(api_token, mpr_url);
+
+    if vote {
+        request.post_checked(&format!("vote/{}", pkgbase), String::new());
+        message::info(&format!("Voted for '{}'.\n", pkgbase.bold().green()));
+    } else {
+        request.post_checked(&format!("unvote/{}", pkgbase), String::new());
+        message::info(&format!(
+            "Removed your vote for '{}'.\n",
+            pkgbase.bold().green()
+        ));
+    }
+}
+
+pub fn vote(args: &clap::ArgMatches) {
+    set_vote(args, true);
+}
+
+pub fn unvote(args: &clap::ArgMatches) {
+    set_vote(args, false);
+}
@@ -8,4 +8,23 @@
 
 @test "list with no results" {
     run ! target/debug/mist list 'nonexistent'
-}
\ No newline at end of file
+}
+
+@test "info with no results" {
+    run ! target/debug/mist info 'nonexistent'
+}
+
+@test "info for a virtual package" {
+    run target/debug/mist info 'mail-transport-agent'
+    [[ "${status}" -eq 64 ]]
+}
+
+@test "search with an unknown field is a text search" {
+    run target/debug/mist search 'nonexistent:field'
+    [[ "${status}" -eq 69 ]]
+}
+
+@test "search with an invalid field value" {
+    run target/debug/mist search 'votes:many'
+    [[ "${status}" -eq 64 ]]
+}